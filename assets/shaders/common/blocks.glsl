@@ -0,0 +1,23 @@
+// Uniform blocks shared by all programs. Their layout must match the structs in
+// src/uniform_blocks.rs, which is checked when a program is linked
+
+// Updated every frame
+layout(std140) uniform Camera {
+  mat4 proj;
+  mat4 view;
+};
+
+// Updated every frame
+layout(std140) uniform Lights {
+  vec3 light_direction;  // in view space
+  vec3 light_ambient;
+  vec3 light_diffuse;
+  vec3 light_specular;
+  vec3 light_radiance;  // PBR light color
+};
+
+// Used by materials which don't set their own values
+layout(std140) uniform MaterialDefaults {
+  vec3 default_specular;
+  float default_shininess;
+};
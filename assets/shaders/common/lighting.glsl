@@ -0,0 +1,64 @@
+// Light types and Blinn/Phong lighting shared by the flat color shaders
+
+struct DirectionalLight {
+  vec3 direction;
+
+  vec3 ambient;
+  vec3 diffuse;
+  vec3 specular;
+};
+
+struct PointLight {
+  vec3 position;
+
+  vec3 ambient;
+  vec3 diffuse;
+  vec3 specular;
+
+  float attn_linear;
+  float attn_quadratic;
+};
+
+vec3 calc_directional_light(DirectionalLight light, vec3 normal, vec3 view_direction,
+                            vec3 ambient_color, vec3 diffuse_color, vec3 specular_color,
+                            float shininess)
+{
+  vec3 light_direction = normalize(-light.direction);
+
+  // Diffuse
+  float diff = max(dot(normal, light_direction), 0.0);
+
+  // Specular
+  vec3 reflection = reflect(-light_direction, normal);
+  float spec = pow(max(dot(view_direction, reflection), 0.0), shininess);
+
+  // Result
+  vec3 ambient = light.ambient * ambient_color;
+  vec3 diffuse = light.diffuse * diff * diffuse_color;
+  vec3 specular = light.specular * spec * specular_color;
+  return (ambient + diffuse + specular);
+}
+
+vec3 calc_point_light(PointLight light, vec3 normal, vec3 frag_pos, vec3 view_direction,
+                      vec3 diffuse_color, vec3 specular_color, float shininess)
+{
+  vec3 light_direction = normalize(light.position - frag_pos);
+
+  // Diffuse
+  float diff = max(dot(normal, light_direction), 0.0);
+
+  // Specular
+  vec3 reflection = reflect(-light_direction, normal);
+  float spec = pow(max(dot(view_direction, reflection), 1.0), shininess);
+
+  // Attenuation
+  float light_distance = length(light.position - frag_pos);
+  float attenuation = 1.0 / (1.0 + light.attn_linear * light_distance +
+                             light.attn_quadratic * (light_distance * light_distance));
+
+  // Result
+  vec3 ambient = light.ambient * diffuse_color;
+  vec3 diffuse = light.diffuse * diff * diffuse_color;
+  vec3 specular = light.specular * spec * specular_color;
+  return (ambient + diffuse + specular) * attenuation;
+}
@@ -0,0 +1,51 @@
+// Vertex inputs and the model matrix for every draw path. Define one of
+// SKINNED, INSTANCED or INDIRECT to pick where the model matrix comes from.
+
+#ifdef INDIRECT
+#extension GL_ARB_shader_draw_parameters : require
+#endif
+
+layout(location = 0) in vec3 Position;
+layout(location = 1) in vec3 Normal;
+layout(location = 2) in vec4 Color;
+layout(location = 3) in vec2 TexCoord;
+
+#ifdef SKINNED
+layout(location = 4) in uvec4 Joints;
+layout(location = 5) in vec4 Weights;
+
+uniform mat4 joint_matrices[MAX_JOINTS];  // MAX_JOINTS is defined by the renderer
+#endif
+
+#ifdef INSTANCED
+layout(location = 6) in mat4 Model;  // per instance, takes locations 6-9
+#endif
+
+#ifdef INDIRECT
+uniform int draw_offset;  // first draw of the current glMultiDrawElementsIndirect call
+
+// Model matrices of all indirect draws, one per draw
+layout(std430, binding = 0) readonly buffer Transforms {
+  mat4 transforms[];
+};
+#endif
+
+#if !defined(INSTANCED) && !defined(INDIRECT)
+uniform mat4 model;
+#endif
+
+mat4 model_matrix() {
+#if defined(INSTANCED)
+  return Model;
+#elif defined(INDIRECT)
+  return transforms[draw_offset + gl_DrawIDARB];
+#elif defined(SKINNED)
+  // Skins with more joints are drawn unskinned, the clamp only guards against bad indices
+  uvec4 joints = min(Joints, uvec4(MAX_JOINTS - 1));
+  mat4 skin = Weights.x * joint_matrices[joints.x] + Weights.y * joint_matrices[joints.y] +
+              Weights.z * joint_matrices[joints.z] + Weights.w * joint_matrices[joints.w];
+  return model * skin;
+#else
+  return model;
+#endif
+}
@@ -4,95 +4,82 @@ in VS_OUTPUT {
   vec3 normal;
   vec3 frag_pos;
   vec3 color;
+  vec2 tex_coord;
 }
 IN;
 
 out vec4 Color;
 
 struct Material {
-  vec3 diffuse;
-  vec3 specular;
-  float shininess;
-};
-
-struct DirectionalLight {
-    vec3 direction;
+  vec4 base_color_factor;
+  sampler2D base_color_texture;
 
-    vec3 ambient;
-    vec3 diffuse;
-    vec3 specular;
-};
+  sampler2D normal_texture;
+  float normal_scale;
 
-struct PointLight {
-  vec3 position;
+  sampler2D occlusion_texture;
+  float occlusion_strength;
 
-  vec3 ambient;
-  vec3 diffuse;
-  vec3 specular;
-
-  float attn_linear;
-  float attn_quadratic;
+  vec3 emissive_factor;
+  sampler2D emissive_texture;
 };
 
+#include "../common/blocks.glsl"
+#include "../common/lighting.glsl"
+
 uniform Material material;
 // uniform PointLight point_light;
-uniform DirectionalLight directional_light;
-
-vec3 calc_directional_light(DirectionalLight light, vec3 normal, vec3 view_direction)
-{
-    vec3 light_direction = normalize(-light.direction);
-    vec3 diffuse_color = IN.color;
-
-    // Diffuse
-    float diff = max(dot(normal, light_direction), 0.0);
-
-    // Specular
-    vec3 reflection = reflect(-light_direction, normal);
-    float spec = pow(max(dot(view_direction, reflection), 0.0), material.shininess);
-
-    // Result
-    vec3 ambient = light.ambient * diffuse_color;
-    vec3 diffuse = light.diffuse * diff * diffuse_color;
-    vec3 specular = light.specular * spec * material.specular;
-    return (ambient + diffuse + specular);
-}
-
-vec3 calc_point_light(PointLight light, vec3 normal, vec3 frag_pos, vec3 view_direction) {
-  vec3 light_direction = normalize(light.position - frag_pos);
-
-  vec3 diffuse_color = IN.color;
-  // vec3 diffuse_color = material.diffuse;
-
-  // Diffuse
-  float diff = max(dot(normal, light_direction), 0.0);
-
-  // Specular
-  vec3 reflection = reflect(-light_direction, normal);
-  float spec = pow(max(dot(view_direction, reflection), 1.0), material.shininess);
-
-  // Attenuation
-  float light_distance = length(light.position - frag_pos);
-  float attenuation = 1.0 / (1.0 + light.attn_linear * light_distance +
-                             light.attn_quadratic * (light_distance * light_distance));
-
-  // Result
-  vec3 ambient = light.ambient * diffuse_color;
-  vec3 diffuse = light.diffuse * diff * diffuse_color;
-  vec3 specular = light.specular * spec * material.specular;
-  return (ambient + diffuse + specular) * attenuation;
+uniform vec3 highlight;  // added to selected nodes
+
+// Normal mapping without tangents: build the tangent frame from screen-space derivatives
+vec3 perturb_normal(vec3 normal, vec3 frag_pos, vec2 uv) {
+  vec3 tangent_normal = texture(material.normal_texture, uv).xyz * 2.0 - 1.0;
+  tangent_normal.xy *= material.normal_scale;
+
+  vec3 dp1 = dFdx(frag_pos);
+  vec3 dp2 = dFdy(frag_pos);
+  vec2 duv1 = dFdx(uv);
+  vec2 duv2 = dFdy(uv);
+
+  vec3 dp2perp = cross(dp2, normal);
+  vec3 dp1perp = cross(normal, dp1);
+  vec3 T = dp2perp * duv1.x + dp1perp * duv2.x;
+  vec3 B = dp2perp * duv1.y + dp1perp * duv2.y;
+  float invmax = inversesqrt(max(dot(T, T), dot(B, B)));
+  if (isinf(invmax)) {
+    return normal;  // no usable texture coordinates
+  }
+  mat3 TBN = mat3(T * invmax, B * invmax, normal);
+  return normalize(TBN * tangent_normal);
 }
 
 void main() {
-  vec3 normal = normalize(IN.normal);
+  vec3 normal = perturb_normal(normalize(IN.normal), IN.frag_pos, IN.tex_coord);
   vec3 view_direction = normalize(-IN.frag_pos);
 
+  vec3 diffuse_color = IN.color * material.base_color_factor.rgb *
+                       texture(material.base_color_texture, IN.tex_coord).rgb;
+  float occlusion = 1.0 + material.occlusion_strength *
+                              (texture(material.occlusion_texture, IN.tex_coord).r - 1.0);
+
   vec3 result_color = vec3(0.0);
 
   // Directional light
-  result_color += calc_directional_light(directional_light, normal, view_direction);
+  DirectionalLight directional_light =
+      DirectionalLight(light_direction, light_ambient, light_diffuse, light_specular);
+  result_color += calc_directional_light(directional_light, normal, view_direction,
+                                         diffuse_color * occlusion, diffuse_color,
+                                         default_specular, default_shininess);
+
+  // Emission
+  result_color += material.emissive_factor * texture(material.emissive_texture, IN.tex_coord).rgb;
 
 //   // Point light
-//   result_color += calc_point_light(point_light, normal, IN.frag_pos, view_direction);
+//   result_color += calc_point_light(point_light, normal, IN.frag_pos, view_direction, IN.color,
+//                                    default_specular, default_shininess);
+
+  // Selection
+  result_color += highlight;
 
   Color = vec4(result_color, 1.0);
 }
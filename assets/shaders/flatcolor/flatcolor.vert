@@ -1,24 +1,23 @@
 #version 330 core
 
-layout(location = 0) in vec3 Position;
-layout(location = 1) in vec3 Normal;
-layout(location = 2) in vec4 Color;
-
-uniform mat4 proj;
-uniform mat4 view;
-uniform mat4 model;
+#include "../common/model.glsl"
+#include "../common/blocks.glsl"
 
 out VS_OUTPUT {
   vec3 normal;
   vec3 frag_pos;
   vec3 color;
+  vec2 tex_coord;
 }
 OUT;
 
 void main() {
-  gl_Position = proj * view * model * vec4(Position, 1.0);
-  OUT.normal = mat3(transpose(inverse(view * model))) * Normal;  // @performance: don't inverse
-  OUT.frag_pos = (view * model * vec4(Position, 1.0)).xyz;
+  mat4 model_view = view * model_matrix();
+
+  gl_Position = proj * model_view * vec4(Position, 1.0);
+  OUT.normal = mat3(transpose(inverse(model_view))) * Normal;  // @performance: don't inverse
+  OUT.frag_pos = (model_view * vec4(Position, 1.0)).xyz;
   OUT.color = Color.xyz * vec3(0.8, 0.8, 0.8);
+  OUT.tex_coord = TexCoord;
 //   OUT.color = vec3(1.0, 0.2, 0.2);
 }
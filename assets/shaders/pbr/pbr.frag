@@ -0,0 +1,144 @@
+#version 330 core
+
+// glTF metallic-roughness model with a Cook-Torrance specular BRDF
+
+in VS_OUTPUT {
+  vec3 normal;
+  vec3 frag_pos;
+  vec4 color;
+  vec2 tex_coord;
+}
+IN;
+
+out vec4 Color;
+
+struct Material {
+  vec4 base_color_factor;
+  sampler2D base_color_texture;
+
+  float metallic_factor;
+  float roughness_factor;
+  sampler2D metallic_roughness_texture;  // B - metallic, G - roughness
+
+  sampler2D normal_texture;
+  float normal_scale;
+
+  sampler2D occlusion_texture;
+  float occlusion_strength;
+
+  vec3 emissive_factor;
+  sampler2D emissive_texture;
+};
+
+struct DirectionalLight {
+  vec3 direction;
+
+  vec3 color;
+  vec3 ambient;
+};
+
+#include "../common/blocks.glsl"
+
+uniform Material material;
+uniform vec3 highlight;  // added to selected nodes
+
+const float PI = 3.14159265359;
+
+// Trowbridge-Reitz GGX normal distribution
+float distribution_ggx(float n_dot_h, float roughness) {
+  float a = roughness * roughness;
+  float a2 = a * a;
+  float denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+  return a2 / (PI * denom * denom);
+}
+
+// Smith's method with Schlick-GGX for both view and light directions
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness) {
+  float r = roughness + 1.0;
+  float k = (r * r) / 8.0;
+  float ggx_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+  float ggx_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+  return ggx_v * ggx_l;
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+  return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+vec3 calc_directional_light(DirectionalLight light, vec3 normal, vec3 view_direction,
+                            vec3 albedo, float metallic, float roughness) {
+  vec3 light_direction = normalize(-light.direction);
+  vec3 halfway = normalize(view_direction + light_direction);
+
+  float n_dot_l = max(dot(normal, light_direction), 0.0);
+  float n_dot_v = max(dot(normal, view_direction), 1e-4);
+  float n_dot_h = max(dot(normal, halfway), 0.0);
+
+  vec3 f0 = mix(vec3(0.04), albedo, metallic);
+  vec3 F = fresnel_schlick(max(dot(halfway, view_direction), 0.0), f0);
+  float D = distribution_ggx(n_dot_h, roughness);
+  float G = geometry_smith(n_dot_v, n_dot_l, roughness);
+
+  vec3 specular = (D * G * F) / (4.0 * n_dot_v * n_dot_l + 1e-4);
+  vec3 k_diffuse = (vec3(1.0) - F) * (1.0 - metallic);
+  vec3 diffuse = k_diffuse * albedo / PI;
+
+  return (diffuse + specular) * light.color * n_dot_l;
+}
+
+// Normal mapping without tangents: build the tangent frame from screen-space derivatives
+vec3 perturb_normal(vec3 normal, vec3 frag_pos, vec2 uv) {
+  vec3 tangent_normal = texture(material.normal_texture, uv).xyz * 2.0 - 1.0;
+  tangent_normal.xy *= material.normal_scale;
+
+  vec3 dp1 = dFdx(frag_pos);
+  vec3 dp2 = dFdy(frag_pos);
+  vec2 duv1 = dFdx(uv);
+  vec2 duv2 = dFdy(uv);
+
+  vec3 dp2perp = cross(dp2, normal);
+  vec3 dp1perp = cross(normal, dp1);
+  vec3 T = dp2perp * duv1.x + dp1perp * duv2.x;
+  vec3 B = dp2perp * duv1.y + dp1perp * duv2.y;
+  float invmax = inversesqrt(max(dot(T, T), dot(B, B)));
+  if (isinf(invmax)) {
+    return normal;  // no usable texture coordinates
+  }
+  mat3 TBN = mat3(T * invmax, B * invmax, normal);
+  return normalize(TBN * tangent_normal);
+}
+
+void main() {
+  vec3 normal = perturb_normal(normalize(IN.normal), IN.frag_pos, IN.tex_coord);
+  vec3 view_direction = normalize(-IN.frag_pos);
+
+  vec4 base_color = IN.color * material.base_color_factor *
+                    texture(material.base_color_texture, IN.tex_coord);
+  vec3 albedo = base_color.rgb;
+
+  vec4 metallic_roughness = texture(material.metallic_roughness_texture, IN.tex_coord);
+  float metallic = clamp(material.metallic_factor * metallic_roughness.b, 0.0, 1.0);
+  float roughness = clamp(material.roughness_factor * metallic_roughness.g, 0.04, 1.0);
+
+  float occlusion = 1.0 + material.occlusion_strength *
+                              (texture(material.occlusion_texture, IN.tex_coord).r - 1.0);
+
+  vec3 result_color = vec3(0.0);
+
+  // Directional light
+  DirectionalLight directional_light =
+      DirectionalLight(light_direction, light_radiance, light_ambient);
+  result_color += calc_directional_light(directional_light, normal, view_direction, albedo,
+                                         metallic, roughness);
+
+  // Ambient
+  result_color += directional_light.ambient * albedo * occlusion;
+
+  // Emission
+  result_color += material.emissive_factor * texture(material.emissive_texture, IN.tex_coord).rgb;
+
+  // Selection
+  result_color += highlight;
+
+  Color = vec4(result_color, 1.0);
+}
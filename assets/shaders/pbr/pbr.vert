@@ -0,0 +1,22 @@
+#version 330 core
+
+#include "../common/model.glsl"
+#include "../common/blocks.glsl"
+
+out VS_OUTPUT {
+  vec3 normal;
+  vec3 frag_pos;
+  vec4 color;
+  vec2 tex_coord;
+}
+OUT;
+
+void main() {
+  mat4 model_view = view * model_matrix();
+
+  gl_Position = proj * model_view * vec4(Position, 1.0);
+  OUT.normal = mat3(transpose(inverse(model_view))) * Normal;  // @performance: don't inverse
+  OUT.frag_pos = (model_view * vec4(Position, 1.0)).xyz;
+  OUT.color = Color;
+  OUT.tex_coord = TexCoord;
+}
@@ -3,8 +3,7 @@ layout (location = 0) in vec3 Position;
 
 out vec3 TexCoords;
 
-uniform mat4 proj;
-uniform mat4 view;
+#include "../common/blocks.glsl"
 
 void main()
 {
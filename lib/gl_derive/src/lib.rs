@@ -0,0 +1,262 @@
+//! Derive macros for the game's GL wrappers. The generated code refers to
+//! `crate::buffers` and `crate::shader`, so they can only be used inside the game crate
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::token::Comma;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Field, Fields, LitInt, LitStr, Result};
+
+// ==================================== Vertex ====================================================
+
+/// Implements `buffers::Vertex` for a `#[repr(C)]` struct. Every field needs either
+/// `#[vertex(location = N)]`, optionally with `normalized` or `integer`, or `#[vertex(skip)]`
+#[proc_macro_derive(Vertex, attributes(vertex))]
+pub fn derive_vertex(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_vertex(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_vertex(input: &DeriveInput) -> Result<TokenStream2> {
+    if !has_repr_c(input) {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "Vertex structs must be #[repr(C)] so field offsets are stable",
+        ));
+    }
+    let fields = named_fields(input, "Vertex")?;
+
+    let mut attributes = Vec::new();
+    for field in fields.iter() {
+        let ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let options = match VertexOptions::parse(field)? {
+            Some(options) => options,
+            None => continue,
+        };
+        let name = ident.to_string();
+        let location = options.location;
+        let normalized = options.normalized;
+        let integer = options.integer;
+        attributes.push(quote! {
+            .attribute(crate::buffers::VertexAttribute {
+                name: #name,
+                location: #location,
+                components: <#ty as crate::buffers::AttributeType>::COMPONENTS,
+                data_type: <#ty as crate::buffers::AttributeType>::GL_TYPE,
+                normalized: #normalized,
+                integer: #integer,
+                offset: std::mem::offset_of!(Self, #ident),
+            })
+        });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics crate::buffers::Vertex for #name #type_generics #where_clause {
+            fn layout() -> crate::buffers::VertexLayout {
+                crate::buffers::VertexLayout::new(std::mem::size_of::<Self>())
+                    #(#attributes)*
+            }
+        }
+    })
+}
+
+fn named_fields<'a>(input: &'a DeriveInput, derive: &str) -> Result<&'a Punctuated<Field, Comma>> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(&fields.named),
+            _ => Err(Error::new_spanned(
+                &input.ident,
+                format!(
+                    "{} can only be derived for structs with named fields",
+                    derive
+                ),
+            )),
+        },
+        _ => Err(Error::new_spanned(
+            &input.ident,
+            format!("{} can only be derived for structs", derive),
+        )),
+    }
+}
+
+fn has_repr_c(input: &DeriveInput) -> bool {
+    input.attrs.iter().any(|attr| {
+        let mut repr_c = false;
+        if attr.path().is_ident("repr") {
+            let _ = attr.parse_nested_meta(|meta| {
+                repr_c |= meta.path.is_ident("C");
+                Ok(())
+            });
+        }
+        repr_c
+    })
+}
+
+/// Contents of `#[vertex(...)]`
+struct VertexOptions {
+    location: u32,
+    normalized: bool,
+    integer: bool,
+}
+
+impl VertexOptions {
+    /// None if the field is skipped
+    fn parse(field: &syn::Field) -> Result<Option<Self>> {
+        let attr = match field
+            .attrs
+            .iter()
+            .find(|attr| attr.path().is_ident("vertex"))
+        {
+            Some(attr) => attr,
+            None => {
+                return Err(Error::new_spanned(
+                    field,
+                    "Missing #[vertex(location = N)] or #[vertex(skip)]",
+                ))
+            }
+        };
+
+        let mut skip = false;
+        let mut location = None;
+        let mut normalized = false;
+        let mut integer = false;
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+            } else if meta.path.is_ident("location") {
+                let value: LitInt = meta.value()?.parse()?;
+                location = Some(value.base10_parse::<u32>()?);
+            } else if meta.path.is_ident("normalized") {
+                normalized = true;
+            } else if meta.path.is_ident("integer") {
+                integer = true;
+            } else {
+                return Err(meta.error("Expected location, normalized, integer or skip"));
+            }
+            Ok(())
+        })?;
+
+        if skip {
+            return Ok(None);
+        }
+        if normalized && integer {
+            return Err(Error::new_spanned(
+                attr,
+                "An attribute can't be both normalized and integer",
+            ));
+        }
+        match location {
+            Some(location) => Ok(Some(VertexOptions {
+                location,
+                normalized,
+                integer,
+            })),
+            None => Err(Error::new_spanned(attr, "Missing location = N")),
+        }
+    }
+}
+
+// ==================================== Uniforms ==================================================
+
+/// Implements `shader::Uniforms`, setting every field as the member of a GLSL struct
+/// with the same name. Fields can be marked with `#[uniform(skip)]`, `#[uniform(optional)]`
+/// if not every program has them, or renamed with `#[uniform(name = "...")]`
+#[proc_macro_derive(Uniforms, attributes(uniform))]
+pub fn derive_uniforms(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_uniforms(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_uniforms(input: &DeriveInput) -> Result<TokenStream2> {
+    let fields = named_fields(input, "Uniforms")?;
+
+    let mut setters = Vec::new();
+    for field in fields.iter() {
+        let ident = field.ident.as_ref().unwrap();
+        let options = match UniformOptions::parse(field)? {
+            Some(options) => options,
+            None => continue,
+        };
+        let glsl_name = options.name.unwrap_or_else(|| ident.to_string());
+        let set = quote! {
+            crate::shader::UniformValue::set_uniform(&self.#ident, program, &name)?;
+        };
+        if options.optional {
+            setters.push(quote! {
+                let name = program.member_name(prefix, #glsl_name);
+                if program.uniform(&name).is_some() {
+                    #set
+                }
+            });
+        } else {
+            setters.push(quote! {
+                let name = program.member_name(prefix, #glsl_name);
+                #set
+            });
+        }
+    }
+
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics crate::shader::Uniforms for #name #type_generics #where_clause {
+            fn set_uniforms(
+                &self,
+                program: &crate::shader::Program,
+                prefix: &str,
+            ) -> crate::shader::Result<()> {
+                #(#setters)*
+                Ok(())
+            }
+        }
+    })
+}
+
+/// Contents of `#[uniform(...)]`
+struct UniformOptions {
+    name: Option<String>,
+    optional: bool,
+}
+
+impl UniformOptions {
+    /// None if the field is skipped
+    fn parse(field: &syn::Field) -> Result<Option<Self>> {
+        let mut options = UniformOptions {
+            name: None,
+            optional: false,
+        };
+        let attr = match field
+            .attrs
+            .iter()
+            .find(|attr| attr.path().is_ident("uniform"))
+        {
+            Some(attr) => attr,
+            None => return Ok(Some(options)),
+        };
+
+        let mut skip = false;
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                skip = true;
+            } else if meta.path.is_ident("optional") {
+                options.optional = true;
+            } else if meta.path.is_ident("name") {
+                let value: LitStr = meta.value()?.parse()?;
+                options.name = Some(value.value());
+            } else {
+                return Err(meta.error("Expected skip, optional or name"));
+            }
+            Ok(())
+        })?;
+
+        Ok(if skip { None } else { Some(options) })
+    }
+}
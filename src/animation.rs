@@ -0,0 +1,739 @@
+use glam::{Mat4, Quat, Vec3, Vec4};
+use gltf::animation::util::ReadOutputs;
+use gltf::animation::Interpolation;
+use thiserror::Error;
+
+// Everything in this module is plain CPU code, no GL calls
+
+// ==================================== Error =====================================================
+
+#[derive(Debug, Error)]
+pub enum AnimationError {
+    #[error("No animation clip named '{0}'")]
+    ClipNotFound(String),
+
+    #[error("Channel {channel} of animation {animation} has {outputs} outputs for {inputs} keyframes, skipped")]
+    OutputCountMismatch {
+        animation: usize,
+        channel: usize,
+        inputs: usize,
+        outputs: usize,
+    },
+}
+
+// ==================================== Transform =================================================
+
+/// Local transform of a node, decomposed into translation, rotation and scale
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    pub fn from_gltf(transform: gltf::scene::Transform) -> Self {
+        let (translation, rotation, scale) = transform.decomposed();
+        Transform {
+            translation: Vec3::from(translation),
+            rotation: Quat::from_array(rotation),
+            scale: Vec3::from(scale),
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::IDENTITY
+    }
+}
+
+// ==================================== Sampling ==================================================
+
+/// A value that can be keyframed
+pub trait Keyframe: Copy {
+    fn lerp(self, other: Self, t: f32) -> Self;
+
+    /// Cubic Hermite spline between p0 and p1.
+    /// The tangents are per second, so they get scaled by the keyframe `duration`
+    fn hermite(p0: Self, m0: Self, p1: Self, m1: Self, t: f32, duration: f32) -> Self;
+}
+
+impl Keyframe for f32 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+
+    fn hermite(p0: Self, m0: Self, p1: Self, m1: Self, t: f32, duration: f32) -> Self {
+        let (a, b, c, d) = hermite_basis(t);
+        a * p0 + b * duration * m0 + c * p1 + d * duration * m1
+    }
+}
+
+impl Keyframe for Vec3 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        Vec3::lerp(self, other, t)
+    }
+
+    fn hermite(p0: Self, m0: Self, p1: Self, m1: Self, t: f32, duration: f32) -> Self {
+        let (a, b, c, d) = hermite_basis(t);
+        a * p0 + b * duration * m0 + c * p1 + d * duration * m1
+    }
+}
+
+impl Keyframe for Quat {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        self.slerp(other, t)
+    }
+
+    fn hermite(p0: Self, m0: Self, p1: Self, m1: Self, t: f32, duration: f32) -> Self {
+        let (a, b, c, d) = hermite_basis(t);
+        let q = a * Vec4::from(p0)
+            + b * duration * Vec4::from(m0)
+            + c * Vec4::from(p1)
+            + d * duration * Vec4::from(m1);
+        Quat::from_vec4(q).normalize()
+    }
+}
+
+fn hermite_basis(t: f32) -> (f32, f32, f32, f32) {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (
+        2.0 * t3 - 3.0 * t2 + 1.0,
+        t3 - 2.0 * t2 + t,
+        -2.0 * t3 + 3.0 * t2,
+        t3 - t2,
+    )
+}
+
+/// Sample a keyframed track at `time`.
+///
+/// For cubic splines `values` holds an (in-tangent, value, out-tangent) triplet per keyframe,
+/// as laid out by glTF. Times outside the track are clamped to the first/last keyframe.
+pub fn sample<T: Keyframe>(
+    times: &[f32],
+    values: &[T],
+    interpolation: Interpolation,
+    time: f32,
+) -> T {
+    let value_at = |i: usize| match interpolation {
+        Interpolation::CubicSpline => values[i * 3 + 1],
+        _ => values[i],
+    };
+
+    let last = times.len() - 1;
+    if time <= times[0] {
+        return value_at(0);
+    }
+    if time >= times[last] {
+        return value_at(last);
+    }
+
+    // Keyframe to the left of time
+    let i = match times.binary_search_by(|t| t.total_cmp(&time)) {
+        Ok(i) => return value_at(i),
+        Err(i) => i - 1,
+    };
+    let duration = times[i + 1] - times[i];
+    let t = (time - times[i]) / duration;
+
+    match interpolation {
+        Interpolation::Step => value_at(i),
+        Interpolation::Linear => value_at(i).lerp(value_at(i + 1), t),
+        Interpolation::CubicSpline => {
+            let p0 = values[i * 3 + 1];
+            let m0 = values[i * 3 + 2]; // out-tangent of the left keyframe
+            let p1 = values[(i + 1) * 3 + 1];
+            let m1 = values[(i + 1) * 3]; // in-tangent of the right keyframe
+            T::hermite(p0, m0, p1, m1, t, duration)
+        }
+    }
+}
+
+// ==================================== Clip ======================================================
+
+#[derive(Debug)]
+pub enum ChannelValues {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+    /// One track per morph target
+    MorphWeights(Vec<Vec<f32>>),
+}
+
+/// Keyframes for one property of one node
+#[derive(Debug)]
+pub struct Channel {
+    pub node_id: usize,
+    pub times: Vec<f32>,
+    pub values: ChannelValues,
+    pub interpolation: Interpolation,
+}
+
+#[derive(Debug)]
+pub struct Clip {
+    pub name: String,
+    pub channels: Vec<Channel>,
+    /// Time of the last keyframe in seconds
+    pub duration: f32,
+}
+
+impl Clip {
+    /// Channels whose output count doesn't match their keyframes are skipped
+    /// and reported in `errors`, sampling them would index past the outputs
+    pub fn from_gltf(
+        animation: gltf::Animation,
+        buffers: &[gltf::buffer::Data],
+        errors: &mut Vec<AnimationError>,
+    ) -> Self {
+        let name = match animation.name() {
+            Some(name) => name.to_owned(),
+            None => format!("animation_{}", animation.index()),
+        };
+
+        let mut channels = Vec::new();
+        for (channel_index, channel) in animation.channels().enumerate() {
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let times: Vec<f32> = match reader.read_inputs() {
+                Some(inputs) => inputs.collect(),
+                None => continue,
+            };
+            if times.is_empty() {
+                continue;
+            }
+            let interpolation = channel.sampler().interpolation();
+            let samples_per_key = match interpolation {
+                Interpolation::CubicSpline => 3,
+                _ => 1,
+            };
+            let expected = times.len() * samples_per_key;
+            // Morph weight outputs hold one value per target for every keyframe sample
+            let (outputs, values, values_per_sample) = match reader.read_outputs() {
+                Some(ReadOutputs::Translations(t)) => {
+                    let values: Vec<Vec3> = t.map(Vec3::from).collect();
+                    (values.len(), ChannelValues::Translation(values), 1)
+                }
+                Some(ReadOutputs::Rotations(r)) => {
+                    let values: Vec<Quat> = r.into_f32().map(Quat::from_array).collect();
+                    (values.len(), ChannelValues::Rotation(values), 1)
+                }
+                Some(ReadOutputs::Scales(s)) => {
+                    let values: Vec<Vec3> = s.map(Vec3::from).collect();
+                    (values.len(), ChannelValues::Scale(values), 1)
+                }
+                Some(ReadOutputs::MorphTargetWeights(w)) => {
+                    // Outputs are interleaved: all targets of keyframe 0, then keyframe 1...
+                    let weights: Vec<f32> = w.into_f32().collect();
+                    let num_targets = (weights.len() / expected).max(1);
+                    let tracks = (0..num_targets)
+                        .map(|target| {
+                            weights
+                                .iter()
+                                .skip(target)
+                                .step_by(num_targets)
+                                .copied()
+                                .collect()
+                        })
+                        .collect();
+                    (
+                        weights.len(),
+                        ChannelValues::MorphWeights(tracks),
+                        num_targets,
+                    )
+                }
+                None => continue,
+            };
+            if outputs != expected * values_per_sample {
+                errors.push(AnimationError::OutputCountMismatch {
+                    animation: animation.index(),
+                    channel: channel_index,
+                    inputs: times.len(),
+                    outputs,
+                });
+                continue;
+            }
+            channels.push(Channel {
+                node_id: channel.target().node().index(),
+                times,
+                values,
+                interpolation,
+            });
+        }
+
+        let duration = channels
+            .iter()
+            .map(|c| *c.times.last().unwrap())
+            .fold(0.0, f32::max);
+
+        Clip {
+            name,
+            channels,
+            duration,
+        }
+    }
+
+    /// Sample every channel at `time` and add the results to the blend accumulators
+    fn accumulate(&self, time: f32, weight: f32, blend: &mut [BlendAccumulator]) {
+        for channel in self.channels.iter() {
+            let acc = &mut blend[channel.node_id];
+            let (times, interpolation) = (&channel.times, channel.interpolation);
+            match &channel.values {
+                ChannelValues::Translation(values) => {
+                    let value = sample(times, values, interpolation, time);
+                    acc.translation += weight * value;
+                    acc.translation_weight += weight;
+                }
+                ChannelValues::Rotation(values) => {
+                    let mut value = Vec4::from(sample(times, values, interpolation, time));
+                    // Keep all quaternions in the same hemisphere so they don't cancel out
+                    if acc.rotation.dot(value) < 0.0 {
+                        value = -value;
+                    }
+                    acc.rotation += weight * value;
+                    acc.rotation_weight += weight;
+                }
+                ChannelValues::Scale(values) => {
+                    let value = sample(times, values, interpolation, time);
+                    acc.scale += weight * value;
+                    acc.scale_weight += weight;
+                }
+                ChannelValues::MorphWeights(tracks) => {
+                    if acc.morph_weights.len() < tracks.len() {
+                        acc.morph_weights.resize(tracks.len(), 0.0);
+                    }
+                    for (sum, track) in acc.morph_weights.iter_mut().zip(tracks.iter()) {
+                        *sum += weight * sample(times, track, interpolation, time);
+                    }
+                    acc.morph_weights_weight += weight;
+                }
+            }
+        }
+    }
+}
+
+/// Weighted sums of sampled values for one node
+#[derive(Debug, Default, Clone)]
+struct BlendAccumulator {
+    translation: Vec3,
+    translation_weight: f32,
+    rotation: Vec4,
+    rotation_weight: f32,
+    scale: Vec3,
+    scale_weight: f32,
+    morph_weights: Vec<f32>,
+    morph_weights_weight: f32,
+}
+
+impl BlendAccumulator {
+    /// Blend the accumulated values into the pose.
+    /// If the total weight is below 1, the rest goes to the pose itself
+    fn resolve(&self, pose: &mut Transform) {
+        if self.translation_weight > 0.0 {
+            let rest = (1.0 - self.translation_weight).max(0.0);
+            let total = self.translation_weight + rest;
+            pose.translation = (self.translation + rest * pose.translation) / total;
+        }
+        if self.rotation_weight > 0.0 {
+            let rest = (1.0 - self.rotation_weight).max(0.0);
+            let mut rest_rotation = Vec4::from(pose.rotation);
+            if self.rotation.dot(rest_rotation) < 0.0 {
+                rest_rotation = -rest_rotation;
+            }
+            pose.rotation = Quat::from_vec4(self.rotation + rest * rest_rotation).normalize();
+        }
+        if self.scale_weight > 0.0 {
+            let rest = (1.0 - self.scale_weight).max(0.0);
+            let total = self.scale_weight + rest;
+            pose.scale = (self.scale + rest * pose.scale) / total;
+        }
+    }
+
+    /// Same as `resolve` for morph target weights
+    fn resolve_morph_weights(&self, weights: &mut Vec<f32>) {
+        if self.morph_weights_weight > 0.0 {
+            let rest = (1.0 - self.morph_weights_weight).max(0.0);
+            let total = self.morph_weights_weight + rest;
+            weights.resize(weights.len().max(self.morph_weights.len()), 0.0);
+            for (i, weight) in weights.iter_mut().enumerate() {
+                let sum = self.morph_weights.get(i).copied().unwrap_or(0.0);
+                *weight = (sum + rest * *weight) / total;
+            }
+        }
+    }
+}
+
+// ==================================== Animator ==================================================
+
+#[derive(Debug, Clone, Copy)]
+struct Playback {
+    time: f32,
+    playing: bool,
+    paused: bool,
+    looping: bool,
+    weight: f32,
+    speed: f32,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Playback {
+            time: 0.0,
+            playing: false,
+            paused: false,
+            looping: true,
+            weight: 1.0,
+            speed: 1.0,
+        }
+    }
+}
+
+/// Plays animation clips by name and blends them into node poses
+#[derive(Debug, Default)]
+pub struct Animator {
+    clips: Vec<Clip>,
+    playback: Vec<Playback>,
+}
+
+impl Animator {
+    /// Invalid channels are skipped and reported in `errors`
+    pub fn from_gltf(
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+        errors: &mut Vec<AnimationError>,
+    ) -> Self {
+        let clips: Vec<Clip> = document
+            .animations()
+            .map(|animation| Clip::from_gltf(animation, buffers, errors))
+            .collect();
+        let playback = vec![Playback::default(); clips.len()];
+        Animator { clips, playback }
+    }
+
+    pub fn clip_names(&self) -> impl Iterator<Item = &str> {
+        self.clips.iter().map(|clip| clip.name.as_str())
+    }
+
+    fn playback_mut(&mut self, name: &str) -> Result<&mut Playback, AnimationError> {
+        let index = self
+            .clips
+            .iter()
+            .position(|clip| clip.name == name)
+            .ok_or_else(|| AnimationError::ClipNotFound(name.to_owned()))?;
+        Ok(&mut self.playback[index])
+    }
+
+    /// Start playing a clip from the beginning
+    pub fn play(&mut self, name: &str) -> Result<(), AnimationError> {
+        let playback = self.playback_mut(name)?;
+        playback.time = 0.0;
+        playback.playing = true;
+        playback.paused = false;
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    /// Stop the clip and remove its influence on the nodes
+    pub fn stop(&mut self, name: &str) -> Result<(), AnimationError> {
+        let playback = self.playback_mut(name)?;
+        playback.playing = false;
+        playback.time = 0.0;
+        Ok(())
+    }
+
+    /// Freeze the clip at its current time. It still affects the nodes
+    pub fn pause(&mut self, name: &str) -> Result<(), AnimationError> {
+        self.playback_mut(name)?.paused = true;
+        Ok(())
+    }
+
+    pub fn resume(&mut self, name: &str) -> Result<(), AnimationError> {
+        self.playback_mut(name)?.paused = false;
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    /// Non-looping clips hold their last frame when they reach the end
+    pub fn set_looping(&mut self, name: &str, looping: bool) -> Result<(), AnimationError> {
+        self.playback_mut(name)?.looping = looping;
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    /// Blend weight of the clip. Nodes keep their own pose for whatever
+    /// is left when the weights of all playing clips add up to less than 1
+    pub fn set_weight(&mut self, name: &str, weight: f32) -> Result<(), AnimationError> {
+        self.playback_mut(name)?.weight = weight.max(0.0);
+        Ok(())
+    }
+
+    #[allow(dead_code)]
+    pub fn set_speed(&mut self, name: &str, speed: f32) -> Result<(), AnimationError> {
+        self.playback_mut(name)?.speed = speed;
+        Ok(())
+    }
+
+    pub fn is_playing(&self, name: &str) -> bool {
+        self.clips
+            .iter()
+            .zip(self.playback.iter())
+            .any(|(clip, playback)| clip.name == name && playback.playing && !playback.paused)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clips.is_empty()
+    }
+
+    #[allow(dead_code)]
+    /// Whether any clip currently affects the nodes
+    pub fn is_active(&self) -> bool {
+        self.playback.iter().any(|p| p.playing && p.weight > 0.0)
+    }
+
+    /// Advance time of all playing clips
+    pub fn advance(&mut self, delta_time: f32) {
+        for (clip, playback) in self.clips.iter().zip(self.playback.iter_mut()) {
+            if !playback.playing || playback.paused {
+                continue;
+            }
+            playback.time += delta_time * playback.speed;
+            if playback.looping && clip.duration > 0.0 {
+                playback.time = playback.time.rem_euclid(clip.duration);
+            } else {
+                playback.time = playback.time.clamp(0.0, clip.duration);
+            }
+        }
+    }
+
+    /// Overwrite the animated properties in `poses` and `morph_weights` (both indexed by node id)
+    /// with blended clip samples
+    pub fn apply(&self, poses: &mut [Transform], morph_weights: &mut [Vec<f32>]) {
+        let mut blend = vec![BlendAccumulator::default(); poses.len()];
+        for (clip, playback) in self.clips.iter().zip(self.playback.iter()) {
+            if playback.playing && playback.weight > 0.0 {
+                clip.accumulate(playback.time, playback.weight, &mut blend);
+            }
+        }
+        for ((acc, pose), weights) in blend
+            .iter()
+            .zip(poses.iter_mut())
+            .zip(morph_weights.iter_mut())
+        {
+            acc.resolve(pose);
+            acc.resolve_morph_weights(weights);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn clip(name: &str, channels: Vec<Channel>) -> Clip {
+        let duration = channels
+            .iter()
+            .map(|c| *c.times.last().unwrap())
+            .fold(0.0, f32::max);
+        Clip {
+            name: name.to_owned(),
+            channels,
+            duration,
+        }
+    }
+
+    fn channel(times: Vec<f32>, values: ChannelValues) -> Channel {
+        Channel {
+            node_id: 0,
+            times,
+            values,
+            interpolation: Interpolation::Linear,
+        }
+    }
+
+    fn animator(clips: Vec<Clip>) -> Animator {
+        let playback = vec![Playback::default(); clips.len()];
+        Animator { clips, playback }
+    }
+
+    /// Samples a track of scalars stored in the x of Vec3 keyframes
+    fn sample_x(times: &[f32], values: &[f32], interpolation: Interpolation, time: f32) -> f32 {
+        let values: Vec<Vec3> = values.iter().map(|&x| Vec3::new(x, 0.0, 0.0)).collect();
+        sample(times, &values, interpolation, time).x
+    }
+
+    fn assert_quat_eq(a: Quat, b: Quat) {
+        // q and -q are the same rotation
+        assert!(a.dot(b).abs() > 1.0 - EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn step_holds_left_keyframe() {
+        let times = [0.0, 1.0, 2.0];
+        let values = [0.0, 10.0, 20.0];
+        let step = Interpolation::Step;
+        assert_eq!(sample_x(&times, &values, step, -1.0), 0.0);
+        assert_eq!(sample_x(&times, &values, step, 0.5), 0.0);
+        assert_eq!(sample_x(&times, &values, step, 1.0), 10.0);
+        assert_eq!(sample_x(&times, &values, step, 1.99), 10.0);
+        assert_eq!(sample_x(&times, &values, step, 3.0), 20.0);
+    }
+
+    #[test]
+    fn linear_interpolates_between_keyframes() {
+        let times = [0.0, 1.0, 3.0];
+        let values = [0.0, 10.0, 20.0];
+        let linear = Interpolation::Linear;
+        assert_eq!(sample_x(&times, &values, linear, -1.0), 0.0);
+        assert_eq!(sample_x(&times, &values, linear, 0.5), 5.0);
+        assert_eq!(sample_x(&times, &values, linear, 1.0), 10.0);
+        assert_eq!(sample_x(&times, &values, linear, 2.0), 15.0);
+        assert_eq!(sample_x(&times, &values, linear, 5.0), 20.0);
+    }
+
+    #[test]
+    fn cubic_spline_uses_values_and_tangents() {
+        let times = [0.0, 2.0, 4.0];
+        // (in-tangent, value, out-tangent) per keyframe
+        let values = [
+            -5.0, 0.0, 1.0, //
+            0.0, 0.0, 0.0, //
+            0.0, 8.0, -5.0,
+        ];
+        let cubic = Interpolation::CubicSpline;
+
+        // Tangents are ignored outside the track and on keyframes
+        assert_eq!(sample_x(&times, &values, cubic, -1.0), 0.0);
+        assert_eq!(sample_x(&times, &values, cubic, 0.0), 0.0);
+        assert_eq!(sample_x(&times, &values, cubic, 2.0), 0.0);
+        assert_eq!(sample_x(&times, &values, cubic, 4.0), 8.0);
+        assert_eq!(sample_x(&times, &values, cubic, 9.0), 8.0);
+
+        // Only the out-tangent of the left keyframe is non-zero: (t^3 - 2t^2 + t) * duration * m0
+        let value = sample_x(&times, &values, cubic, 1.0);
+        assert!((value - 0.125 * 2.0).abs() < EPSILON);
+
+        // Zero tangents: smoothstep between the values
+        let value = sample_x(&times, &values, cubic, 3.0);
+        assert!((value - 4.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn rotations_are_slerped() {
+        let times = [0.0, 1.0];
+        let values = [Quat::IDENTITY, Quat::from_rotation_z(90f32.to_radians())];
+        let value = sample(&times, &values, Interpolation::Linear, 0.5);
+        assert_quat_eq(value, Quat::from_rotation_z(45f32.to_radians()));
+    }
+
+    #[test]
+    fn opposite_quaternions_dont_cancel_out() {
+        let rotation = Quat::from_rotation_y(1.0);
+        let a = clip(
+            "a",
+            vec![channel(vec![0.0], ChannelValues::Rotation(vec![rotation]))],
+        );
+        let b = clip(
+            "b",
+            vec![channel(vec![0.0], ChannelValues::Rotation(vec![-rotation]))],
+        );
+
+        let mut blend = vec![BlendAccumulator::default()];
+        a.accumulate(0.0, 0.5, &mut blend);
+        b.accumulate(0.0, 0.5, &mut blend);
+        let mut pose = Transform::IDENTITY;
+        blend[0].resolve(&mut pose);
+        assert_quat_eq(pose.rotation, rotation);
+    }
+
+    #[test]
+    fn rest_pose_rotation_is_flipped_into_the_same_hemisphere() {
+        let rotation = Quat::from_rotation_x(0.5);
+        let a = clip(
+            "a",
+            vec![channel(vec![0.0], ChannelValues::Rotation(vec![rotation]))],
+        );
+
+        let mut blend = vec![BlendAccumulator::default()];
+        a.accumulate(0.0, 0.5, &mut blend);
+        let mut pose = Transform {
+            rotation: -rotation,
+            ..Transform::IDENTITY
+        };
+        blend[0].resolve(&mut pose);
+        assert_quat_eq(pose.rotation, rotation);
+    }
+
+    #[test]
+    fn weights_below_one_blend_with_the_pose() {
+        let a = clip(
+            "a",
+            vec![channel(
+                vec![0.0],
+                ChannelValues::Translation(vec![Vec3::new(4.0, 0.0, 0.0)]),
+            )],
+        );
+        let b = clip(
+            "b",
+            vec![channel(
+                vec![0.0],
+                ChannelValues::Translation(vec![Vec3::new(0.0, 4.0, 0.0)]),
+            )],
+        );
+        let mut animator = animator(vec![a, b]);
+        animator.play("a").unwrap();
+        animator.play("b").unwrap();
+        animator.set_weight("a", 0.25).unwrap();
+        animator.set_weight("b", 0.25).unwrap();
+
+        // Half of the result comes from the pose
+        let mut poses = [Transform {
+            translation: Vec3::new(0.0, 0.0, 2.0),
+            ..Transform::IDENTITY
+        }];
+        let mut morph_weights = [Vec::new()];
+        animator.apply(&mut poses, &mut morph_weights);
+        let expected = Vec3::new(1.0, 1.0, 1.0);
+        assert!(poses[0].translation.abs_diff_eq(expected, EPSILON));
+        assert_eq!(poses[0].rotation, Quat::IDENTITY);
+        assert_eq!(poses[0].scale, Vec3::ONE);
+    }
+
+    #[test]
+    fn looping_clips_wrap_and_others_clamp() {
+        let track = || {
+            channel(
+                vec![0.0, 2.0],
+                ChannelValues::Scale(vec![Vec3::ONE, Vec3::ONE]),
+            )
+        };
+        let mut animator = animator(vec![
+            clip("loop", vec![track()]),
+            clip("once", vec![track()]),
+        ]);
+        animator.set_looping("once", false).unwrap();
+        animator.play("loop").unwrap();
+        animator.play("once").unwrap();
+
+        animator.advance(2.5);
+        assert!((animator.playback[0].time - 0.5).abs() < EPSILON);
+        assert_eq!(animator.playback[1].time, 2.0);
+
+        animator.set_speed("loop", -1.0).unwrap();
+        animator.advance(1.0);
+        assert!((animator.playback[0].time - 1.5).abs() < EPSILON);
+        assert_eq!(animator.playback[1].time, 2.0);
+        assert!(animator.is_playing("once"));
+    }
+}
@@ -1,40 +1,401 @@
+use std::cell::Cell;
+use std::marker::PhantomData;
+
+use bytemuck::Pod;
 use gl::types::*;
+use glam::{Vec2, Vec3, Vec4};
+
+use crate::utils::{
+    gl_supports_attrib_binding, gl_supports_buffer_storage, gl_supports_dsa, gl_version,
+    track_gl_object_created, track_gl_object_deleted, GlObject,
+};
 
 // ==================================== Buffer ====================================================
 
-/// An OpenGL buffer
+/// How often the contents of a buffer are expected to change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferUsage {
+    /// Uploaded once, drawn many times
+    Static,
+    /// Updated from time to time
+    Dynamic,
+    #[allow(dead_code)]
+    /// Rewritten every frame
+    Stream,
+}
+
+impl BufferUsage {
+    fn as_gl_enum(self) -> GLenum {
+        match self {
+            BufferUsage::Static => gl::STATIC_DRAW,
+            BufferUsage::Dynamic => gl::DYNAMIC_DRAW,
+            BufferUsage::Stream => gl::STREAM_DRAW,
+        }
+    }
+}
+
+/// An OpenGL buffer holding an array of `T`
 #[derive(Debug)]
-pub struct Buffer {
+pub struct Buffer<T: Pod = u8> {
     /// ID of the buffer in OpenGL
     id: GLuint,
+    /// Number of elements the storage has room for
+    len: Cell<usize>,
+    usage: BufferUsage,
+    _marker: PhantomData<T>,
 }
 
-impl Buffer {
-    pub fn create(data_ptr: *const u8, length: usize) -> Self {
-        let mut id: GLuint = 0;
+impl<T: Pod> Buffer<T> {
+    pub fn new(data: &[T], usage: BufferUsage) -> Self {
+        let buffer = Buffer {
+            id: create_buffer(),
+            len: Cell::new(0),
+            usage,
+            _marker: PhantomData,
+        };
+        buffer.replace(data);
+        buffer
+    }
+
+    #[allow(dead_code)]
+    /// Creates a buffer with undefined contents
+    pub fn with_capacity(len: usize, usage: BufferUsage) -> Self {
+        let buffer = Buffer::new(&[], usage);
+        buffer.allocate(len, std::ptr::null());
+        buffer
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[allow(dead_code)]
+    pub fn usage(&self) -> BufferUsage {
+        self.usage
+    }
+
+    /// Overwrites the elements starting at `offset`, which must fit into the buffer
+    pub fn update(&self, offset: usize, data: &[T]) {
+        assert!(
+            offset + data.len() <= self.len(),
+            "Buffer update out of range: {}..{} of {}",
+            offset,
+            offset + data.len(),
+            self.len()
+        );
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        buffer_sub_data(self.id, offset * std::mem::size_of::<T>(), bytes);
+    }
+
+    /// Reallocates the buffer with new contents. The old storage is orphaned,
+    /// so there's no waiting for draws which still use it
+    pub fn replace(&self, data: &[T]) {
+        self.allocate(data.len(), data.as_ptr() as *const GLvoid);
+    }
+
+    #[allow(dead_code)]
+    /// Gives the buffer fresh storage of the same size with undefined contents.
+    /// Call before rewriting the whole buffer with `update`
+    pub fn orphan(&self) {
+        self.allocate(self.len(), std::ptr::null());
+    }
+
+    fn allocate(&self, len: usize, data_ptr: *const GLvoid) {
+        let size = len * std::mem::size_of::<T>();
+        buffer_data(self.id, size, data_ptr, self.usage.as_gl_enum());
+        self.len.set(len);
+    }
+
+    #[allow(dead_code)]
+    pub fn bind_as_array_buffer(&self) {
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.id);
+        }
+    }
+
+    pub fn bind_as_ebo(&self) {
         unsafe {
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.id);
+        }
+    }
+
+    /// Bind to the uniform block with `binding = index`
+    pub fn bind_as_uniform_buffer(&self, index: u32) {
+        unsafe {
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, index, self.id);
+        }
+    }
+}
+
+impl<T: Pod> Drop for Buffer<T> {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.id);
+        }
+        track_gl_object_deleted(GlObject::Buffer, self.id);
+    }
+}
+
+/// glGenBuffers, or glCreateBuffers which also creates the buffer object without binding it
+fn create_buffer() -> GLuint {
+    let mut id: GLuint = 0;
+    unsafe {
+        if gl_supports_dsa() {
+            gl::CreateBuffers(1, &mut id);
+        } else {
             gl::GenBuffers(1, &mut id);
+        }
+    }
+    track_gl_object_created(GlObject::Buffer, id);
+    id
+}
+
+fn buffer_data(id: GLuint, size: usize, data_ptr: *const GLvoid, usage: GLenum) {
+    unsafe {
+        if gl_supports_dsa() {
+            gl::NamedBufferData(id, size as isize, data_ptr, usage);
+        } else {
+            gl::BindBuffer(gl::ARRAY_BUFFER, id);
+            gl::BufferData(gl::ARRAY_BUFFER, size as isize, data_ptr, usage);
+        }
+    }
+}
+
+fn buffer_sub_data(id: GLuint, offset: usize, bytes: &[u8]) {
+    let (offset, size, data_ptr) = (
+        offset as isize,
+        bytes.len() as isize,
+        bytes.as_ptr() as *const GLvoid,
+    );
+    unsafe {
+        if gl_supports_dsa() {
+            gl::NamedBufferSubData(id, offset, size, data_ptr);
+        } else {
             gl::BindBuffer(gl::ARRAY_BUFFER, id);
-            gl::BufferData(
-                gl::ARRAY_BUFFER,
-                length as isize,
-                data_ptr as *const GLvoid,
-                gl::STATIC_DRAW,
+            gl::BufferSubData(gl::ARRAY_BUFFER, offset, size, data_ptr);
+        }
+    }
+}
+
+// ==================================== RingBuffer ================================================
+
+/// Sections of a ring buffer: the CPU writes one while the GPU may still read the others
+const RING_FRAMES: usize = 3;
+
+/// Where data pushed to a ring buffer ended up, in bytes from the start of the buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RingSlice {
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// Per-frame streaming data. Each frame writes to its own section of a persistently mapped
+/// buffer, fenced so it isn't overwritten while the GPU reads it. Without glBufferStorage
+/// there is one section which is orphaned every frame and written with glBufferSubData
+#[derive(Debug)]
+pub struct RingBuffer {
+    id: GLuint,
+    /// Size of each section in bytes
+    frame_size: usize,
+    /// None if the buffer is orphaned instead
+    mapping: Option<*mut u8>,
+    /// Signalled when the GPU is done with the frame written to the section
+    fences: [GLsync; RING_FRAMES],
+    frame: usize,
+    /// Bytes taken in the current section
+    used: usize,
+    uniform_alignment: usize,
+    storage_alignment: usize,
+}
+
+impl RingBuffer {
+    pub fn new(frame_size: usize) -> Self {
+        let mut id = create_buffer();
+        let mut uniform_alignment: GLint = 1;
+        let mut storage_alignment: GLint = 1;
+        unsafe {
+            gl::GetIntegerv(gl::UNIFORM_BUFFER_OFFSET_ALIGNMENT, &mut uniform_alignment);
+            if gl_version() >= (4, 3) {
+                gl::GetIntegerv(
+                    gl::SHADER_STORAGE_BUFFER_OFFSET_ALIGNMENT,
+                    &mut storage_alignment,
+                );
+            }
+        }
+
+        let mut mapping = None;
+        if gl_supports_buffer_storage() {
+            let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+            let size = (frame_size * RING_FRAMES) as isize;
+            let ptr = unsafe {
+                if gl_supports_dsa() {
+                    gl::NamedBufferStorage(id, size, std::ptr::null(), flags);
+                    gl::MapNamedBufferRange(id, 0, size, flags)
+                } else {
+                    gl::BindBuffer(gl::ARRAY_BUFFER, id);
+                    gl::BufferStorage(gl::ARRAY_BUFFER, size, std::ptr::null(), flags);
+                    gl::MapBufferRange(gl::ARRAY_BUFFER, 0, size, flags)
+                }
+            } as *mut u8;
+            if ptr.is_null() {
+                // Immutable storage can't be orphaned, the fallback needs a new buffer
+                eprintln!(
+                    "Warning: couldn't map a ring buffer of {} bytes, orphaning it instead",
+                    size
+                );
+                unsafe {
+                    // Clear the error the failed map raised, gl_check_error! would panic on it
+                    while gl::GetError() != gl::NO_ERROR {}
+                    gl::DeleteBuffers(1, &id);
+                }
+                track_gl_object_deleted(GlObject::Buffer, id);
+                id = create_buffer();
+            } else {
+                mapping = Some(ptr);
+            }
+        }
+        if mapping.is_none() {
+            buffer_data(id, frame_size, std::ptr::null(), gl::STREAM_DRAW);
+        }
+
+        RingBuffer {
+            id,
+            frame_size,
+            mapping,
+            fences: [std::ptr::null(); RING_FRAMES],
+            frame: 0,
+            used: 0,
+            uniform_alignment: uniform_alignment as usize,
+            storage_alignment: storage_alignment as usize,
+        }
+    }
+
+    /// Bytes which can be pushed each frame
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    /// Offsets of slices bound as uniform buffers must be multiples of this
+    pub fn uniform_alignment(&self) -> usize {
+        self.uniform_alignment
+    }
+
+    /// Offsets of slices bound as shader storage buffers must be multiples of this
+    pub fn storage_alignment(&self) -> usize {
+        self.storage_alignment
+    }
+
+    /// Move to the next section, waiting if the GPU still reads it
+    pub fn begin_frame(&mut self) {
+        self.used = 0;
+        if self.mapping.is_none() {
+            buffer_data(self.id, self.frame_size, std::ptr::null(), gl::STREAM_DRAW);
+            return;
+        }
+
+        self.frame = (self.frame + 1) % RING_FRAMES;
+        let fence = std::mem::replace(&mut self.fences[self.frame], std::ptr::null());
+        if fence.is_null() {
+            return;
+        }
+        unsafe {
+            loop {
+                let result = gl::ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, 1_000_000);
+                if result != gl::TIMEOUT_EXPIRED {
+                    break; // signalled or failed, there's nothing else to wait for
+                }
+            }
+            gl::DeleteSync(fence);
+        }
+    }
+
+    /// Fence the section after the draws reading it have been submitted
+    pub fn end_frame(&mut self) {
+        if self.mapping.is_some() {
+            self.fences[self.frame] = unsafe { gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0) };
+        }
+    }
+
+    /// Copy the data into the current section with its offset rounded up to the alignment.
+    /// Returns None if the section is full
+    pub fn push<T: Pod>(&mut self, data: &[T], alignment: usize) -> Option<RingSlice> {
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        let start = self.used.next_multiple_of(alignment.max(1));
+        if start + bytes.len() > self.frame_size {
+            return None;
+        }
+        self.used = start + bytes.len();
+
+        let slice = match self.mapping {
+            Some(ptr) => {
+                let offset = self.frame * self.frame_size + start;
+                unsafe {
+                    std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr.add(offset), bytes.len());
+                }
+                RingSlice {
+                    offset,
+                    size: bytes.len(),
+                }
+            }
+            None => {
+                buffer_sub_data(self.id, start, bytes);
+                RingSlice {
+                    offset: start,
+                    size: bytes.len(),
+                }
+            }
+        };
+        Some(slice)
+    }
+
+    pub fn bind_as_indirect_buffer(&self) {
+        unsafe {
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, self.id);
+        }
+    }
+
+    /// Bind the slice to the uniform block with `binding = index`
+    pub fn bind_as_uniform_buffer(&self, index: u32, slice: RingSlice) {
+        unsafe {
+            gl::BindBufferRange(
+                gl::UNIFORM_BUFFER,
+                index,
+                self.id,
+                slice.offset as isize,
+                slice.size as isize,
             );
         }
-        Buffer { id }
     }
 
-    pub fn bind_as_array_buffer(&self) {
+    /// Bind the slice to the shader storage block with `binding = index`
+    pub fn bind_as_storage_buffer(&self, index: u32, slice: RingSlice) {
         unsafe {
-            gl::BindBuffer(gl::ARRAY_BUFFER, self.id);
+            gl::BindBufferRange(
+                gl::SHADER_STORAGE_BUFFER,
+                index,
+                self.id,
+                slice.offset as isize,
+                slice.size as isize,
+            );
         }
     }
+}
 
-    pub fn bind_as_ebo(&self) {
+impl Drop for RingBuffer {
+    fn drop(&mut self) {
         unsafe {
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.id);
+            for &fence in self.fences.iter().filter(|fence| !fence.is_null()) {
+                gl::DeleteSync(fence);
+            }
+            // Deleting a buffer unmaps it
+            gl::DeleteBuffers(1, &self.id);
         }
+        track_gl_object_deleted(GlObject::Buffer, self.id);
     }
 }
 
@@ -47,6 +408,188 @@ pub struct ElementBuffer {
     pub element_type: GLenum,
 }
 
+// ==================================== VertexLayout ==============================================
+
+/// Implemented with `#[derive(Vertex)]` for `#[repr(C)]` vertex structs
+pub trait Vertex: Pod {
+    fn layout() -> VertexLayout;
+}
+
+/// Rust types which can be read as a vertex attribute
+pub trait AttributeType: Pod {
+    const COMPONENTS: i32;
+    const GL_TYPE: GLenum;
+}
+
+macro_rules! impl_attribute_type {
+    ($t:ty, $gl_type:expr) => {
+        impl AttributeType for $t {
+            const COMPONENTS: i32 = 1;
+            const GL_TYPE: GLenum = $gl_type;
+        }
+        impl AttributeType for [$t; 2] {
+            const COMPONENTS: i32 = 2;
+            const GL_TYPE: GLenum = $gl_type;
+        }
+        impl AttributeType for [$t; 3] {
+            const COMPONENTS: i32 = 3;
+            const GL_TYPE: GLenum = $gl_type;
+        }
+        impl AttributeType for [$t; 4] {
+            const COMPONENTS: i32 = 4;
+            const GL_TYPE: GLenum = $gl_type;
+        }
+    };
+}
+
+impl_attribute_type!(f32, gl::FLOAT);
+impl_attribute_type!(i8, gl::BYTE);
+impl_attribute_type!(u8, gl::UNSIGNED_BYTE);
+impl_attribute_type!(i16, gl::SHORT);
+impl_attribute_type!(u16, gl::UNSIGNED_SHORT);
+impl_attribute_type!(i32, gl::INT);
+impl_attribute_type!(u32, gl::UNSIGNED_INT);
+
+impl AttributeType for Vec2 {
+    const COMPONENTS: i32 = 2;
+    const GL_TYPE: GLenum = gl::FLOAT;
+}
+
+impl AttributeType for Vec3 {
+    const COMPONENTS: i32 = 3;
+    const GL_TYPE: GLenum = gl::FLOAT;
+}
+
+impl AttributeType for Vec4 {
+    const COMPONENTS: i32 = 4;
+    const GL_TYPE: GLenum = gl::FLOAT;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VertexAttribute {
+    /// Only used in debug output
+    pub name: &'static str,
+    pub location: u32,
+    /// 1 to 4
+    pub components: i32,
+    /// Type of each component, e.g. gl::FLOAT
+    pub data_type: GLenum,
+    /// Integers are mapped to [0, 1] or [-1, 1]
+    pub normalized: bool,
+    /// Read as integers by the shader (ivec, uvec)
+    pub integer: bool,
+    /// Bytes from the start of the vertex, or of the attribute's block if the stride is 0
+    pub offset: usize,
+}
+
+impl VertexAttribute {
+    /// An attribute with the components of `T`, neither normalized nor integer
+    pub fn new<T: AttributeType>(name: &'static str, location: u32, offset: usize) -> Self {
+        VertexAttribute {
+            name,
+            location,
+            components: T::COMPONENTS,
+            data_type: T::GL_TYPE,
+            normalized: false,
+            integer: false,
+            offset,
+        }
+    }
+}
+
+/// How attributes are read from an array buffer
+#[derive(Debug, Clone, PartialEq)]
+pub struct VertexLayout {
+    attributes: Vec<VertexAttribute>,
+    /// Bytes between vertices, 0 if each attribute is tightly packed
+    stride: usize,
+    /// Advance once per this many instances, 0 for every vertex
+    divisor: u32,
+}
+
+impl VertexLayout {
+    pub fn new(stride: usize) -> Self {
+        VertexLayout {
+            attributes: Vec::new(),
+            stride,
+            divisor: 0,
+        }
+    }
+
+    pub fn attribute(mut self, attribute: VertexAttribute) -> Self {
+        self.attributes.push(attribute);
+        self
+    }
+
+    pub fn divisor(mut self, divisor: u32) -> Self {
+        self.divisor = divisor;
+        self
+    }
+
+    /// Binding point of an interleaved layout read through `set_ring_format`.
+    /// Separate layouts use separate bindings, so the first location is used
+    fn binding(&self) -> GLuint {
+        self.attributes
+            .first()
+            .map_or(0, |attribute| attribute.location)
+    }
+
+    /// Describe the attributes in the bound vertex array, reading from the bound array buffer
+    /// with all offsets moved by `base_offset`
+    fn apply(&self, base_offset: usize) {
+        self.set_pointers(base_offset);
+        for attribute in self.attributes.iter() {
+            unsafe {
+                gl::VertexAttribDivisor(attribute.location, self.divisor);
+                gl::EnableVertexAttribArray(attribute.location);
+            }
+        }
+    }
+
+    /// Point the attributes at the bound array buffer, without touching divisors
+    fn set_pointers(&self, base_offset: usize) {
+        let stride = self.stride as GLsizei;
+        for attribute in self.attributes.iter() {
+            let offset = (base_offset + attribute.offset) as *const GLvoid;
+            unsafe {
+                if attribute.integer {
+                    gl::VertexAttribIPointer(
+                        attribute.location,
+                        attribute.components,
+                        attribute.data_type,
+                        stride,
+                        offset,
+                    );
+                } else {
+                    let normalized = if attribute.normalized {
+                        gl::TRUE
+                    } else {
+                        gl::FALSE
+                    };
+                    gl::VertexAttribPointer(
+                        attribute.location,
+                        attribute.components,
+                        attribute.data_type,
+                        normalized,
+                        stride,
+                        offset,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Size in bytes of a vertex attribute component type
+fn gl_type_size(data_type: GLenum) -> usize {
+    match data_type {
+        gl::BYTE | gl::UNSIGNED_BYTE => 1,
+        gl::SHORT | gl::UNSIGNED_SHORT | gl::HALF_FLOAT => 2,
+        gl::DOUBLE => 8,
+        _ => 4,
+    }
+}
+
 // ==================================== VertexArray ===============================================
 
 #[derive(Debug)]
@@ -58,8 +601,13 @@ impl VertexArray {
     pub fn new() -> Self {
         let mut id: GLuint = 0;
         unsafe {
-            gl::GenVertexArrays(1, &mut id);
+            if gl_supports_dsa() {
+                gl::CreateVertexArrays(1, &mut id);
+            } else {
+                gl::GenVertexArrays(1, &mut id);
+            }
         }
+        track_gl_object_created(GlObject::VertexArray, id);
         VertexArray { id }
     }
 
@@ -74,4 +622,182 @@ impl VertexArray {
             gl::BindVertexArray(0);
         }
     }
+
+    /// Read the layout's attributes from the buffer.
+    /// Without DSA this leaves the vertex array bound
+    pub fn set_layout<T: Pod>(&self, buffer: &Buffer<T>, layout: &VertexLayout) {
+        self.set_layout_at(buffer.id, 0, layout);
+    }
+
+    /// Describe an interleaved layout whose buffer changes between draws, such as
+    /// per-instance data in the ring buffer. All attributes share one binding point,
+    /// which `bind_ring_slice` then points at a slice.
+    /// Without attribute bindings (GL < 4.3) only the divisors are set here
+    pub fn set_ring_format(&self, layout: &VertexLayout) {
+        let binding = layout.binding();
+        for attribute in layout.attributes.iter() {
+            let (location, offset) = (attribute.location, attribute.offset as GLuint);
+            let normalized = if attribute.normalized {
+                gl::TRUE
+            } else {
+                gl::FALSE
+            };
+            unsafe {
+                if gl_supports_dsa() {
+                    if attribute.integer {
+                        gl::VertexArrayAttribIFormat(
+                            self.id,
+                            location,
+                            attribute.components,
+                            attribute.data_type,
+                            offset,
+                        );
+                    } else {
+                        gl::VertexArrayAttribFormat(
+                            self.id,
+                            location,
+                            attribute.components,
+                            attribute.data_type,
+                            normalized,
+                            offset,
+                        );
+                    }
+                    gl::VertexArrayAttribBinding(self.id, location, binding);
+                    gl::EnableVertexArrayAttrib(self.id, location);
+                } else if gl_supports_attrib_binding() {
+                    self.bind();
+                    if attribute.integer {
+                        gl::VertexAttribIFormat(
+                            location,
+                            attribute.components,
+                            attribute.data_type,
+                            offset,
+                        );
+                    } else {
+                        gl::VertexAttribFormat(
+                            location,
+                            attribute.components,
+                            attribute.data_type,
+                            normalized,
+                            offset,
+                        );
+                    }
+                    gl::VertexAttribBinding(location, binding);
+                    gl::EnableVertexAttribArray(location);
+                } else {
+                    self.bind();
+                    gl::VertexAttribDivisor(location, layout.divisor);
+                    gl::EnableVertexAttribArray(location);
+                }
+            }
+        }
+        unsafe {
+            if gl_supports_dsa() {
+                gl::VertexArrayBindingDivisor(self.id, binding, layout.divisor);
+            } else if gl_supports_attrib_binding() {
+                gl::VertexBindingDivisor(binding, layout.divisor);
+            }
+        }
+    }
+
+    /// Read a layout set up with `set_ring_format` from a slice of the ring buffer.
+    /// Without attribute bindings the pointers have to be specified again
+    pub fn bind_ring_slice(&self, ring: &RingBuffer, slice: RingSlice, layout: &VertexLayout) {
+        let stride = layout.stride as GLsizei;
+        unsafe {
+            if gl_supports_dsa() {
+                gl::VertexArrayVertexBuffer(
+                    self.id,
+                    layout.binding(),
+                    ring.id,
+                    slice.offset as isize,
+                    stride,
+                );
+            } else if gl_supports_attrib_binding() {
+                self.bind();
+                gl::BindVertexBuffer(layout.binding(), ring.id, slice.offset as isize, stride);
+            } else {
+                self.bind();
+                gl::BindBuffer(gl::ARRAY_BUFFER, ring.id);
+                layout.set_pointers(slice.offset);
+            }
+        }
+    }
+
+    pub fn set_element_buffer<T: Pod>(&self, buffer: &Buffer<T>) {
+        unsafe {
+            if gl_supports_dsa() {
+                gl::VertexArrayElementBuffer(self.id, buffer.id);
+            } else {
+                self.bind();
+                buffer.bind_as_ebo();
+            }
+        }
+    }
+
+    fn set_layout_at(&self, buffer: GLuint, base_offset: usize, layout: &VertexLayout) {
+        if !gl_supports_dsa() {
+            self.bind();
+            unsafe {
+                gl::BindBuffer(gl::ARRAY_BUFFER, buffer);
+            }
+            layout.apply(base_offset);
+            return;
+        }
+
+        // Each attribute gets its own binding point, so tightly packed blocks
+        // and interleaved vertices are handled the same way
+        for attribute in layout.attributes.iter() {
+            let binding = attribute.location;
+            let stride = if layout.stride == 0 {
+                attribute.components as usize * gl_type_size(attribute.data_type)
+            } else {
+                layout.stride
+            };
+            unsafe {
+                gl::VertexArrayVertexBuffer(
+                    self.id,
+                    binding,
+                    buffer,
+                    (base_offset + attribute.offset) as isize,
+                    stride as GLsizei,
+                );
+                if attribute.integer {
+                    gl::VertexArrayAttribIFormat(
+                        self.id,
+                        attribute.location,
+                        attribute.components,
+                        attribute.data_type,
+                        0,
+                    );
+                } else {
+                    let normalized = if attribute.normalized {
+                        gl::TRUE
+                    } else {
+                        gl::FALSE
+                    };
+                    gl::VertexArrayAttribFormat(
+                        self.id,
+                        attribute.location,
+                        attribute.components,
+                        attribute.data_type,
+                        normalized,
+                        0,
+                    );
+                }
+                gl::VertexArrayAttribBinding(self.id, attribute.location, binding);
+                gl::VertexArrayBindingDivisor(self.id, binding, layout.divisor);
+                gl::EnableVertexArrayAttrib(self.id, attribute.location);
+            }
+        }
+    }
+}
+
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.id);
+        }
+        track_gl_object_deleted(GlObject::VertexArray, self.id);
+    }
 }
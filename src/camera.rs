@@ -4,6 +4,8 @@ use std::f32::consts::PI;
 
 use glam::{const_vec3, Mat4, Vec2, Vec3};
 
+use crate::geometry::Ray;
+
 const FOV_MIN: f32 = 0.01 * PI;
 const FOV_MAX: f32 = 0.5 * PI;
 
@@ -148,6 +150,19 @@ impl Camera {
         (1.0 - t) * FOV_MAX + t * FOV_MIN
     }
 
+    pub fn screen_center(&self) -> Vec2 {
+        self.screen_dimensions / 2.0
+    }
+
+    /// Ray from the camera through a point on the screen, in pixels from the top left corner
+    pub fn screen_ray(&self, screen_position: Vec2) -> Ray {
+        let ndc = screen_position / self.screen_dimensions * 2.0 - Vec2::ONE;
+        let tan_half_fov = (self.v_fov / 2.0).tan();
+        let direction = self.direction + self.right * (ndc.x * tan_half_fov * self.aspect_ratio)
+            - self.up * (ndc.y * tan_half_fov); // screen y points down
+        Ray::new(self.position, direction.normalize())
+    }
+
     pub fn get_view_matrix(&self) -> Mat4 {
         // Camera never turns upside down so true up is fixed
         Mat4::look_at_rh(self.position, self.position + self.direction, TRUE_UP)
@@ -167,3 +182,36 @@ impl Camera {
         Mat4::perspective_infinite_rh(self.v_fov, self.aspect_ratio, 0.5)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screen_ray_projects_back_to_the_pixel() {
+        let camera = Camera::new(
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(4.0, 0.0, -5.0),
+            800,
+            600,
+        );
+        let view_projection = camera.get_projection_matrix() * camera.get_view_matrix();
+
+        let center = camera.screen_ray(camera.screen_center());
+        assert!(center.direction.abs_diff_eq(camera.direction, 1e-5));
+
+        // Screen y points down, NDC y points up
+        let pixels = [
+            (0.0, 0.0, -1.0, 1.0),
+            (800.0, 600.0, 1.0, -1.0),
+            (200.0, 450.0, -0.5, -0.5),
+        ];
+        for &(x, y, ndc_x, ndc_y) in pixels.iter() {
+            let ray = camera.screen_ray(Vec2::new(x, y));
+            assert_eq!(ray.origin, camera.position);
+            let clip = view_projection * ray.at(10.0).extend(1.0);
+            let ndc = Vec2::new(clip.x, clip.y) / clip.w;
+            assert!(ndc.abs_diff_eq(Vec2::new(ndc_x, ndc_y), 1e-4), "{:?}", ndc);
+        }
+    }
+}
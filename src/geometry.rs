@@ -0,0 +1,473 @@
+use glam::{const_vec3, Mat4, Vec3, Vec4};
+
+// ==================================== Aabb ======================================================
+
+/// Axis-aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Contains nothing, a union with any box gives that box
+    pub const EMPTY: Aabb = Aabb {
+        min: const_vec3!([f32::INFINITY, f32::INFINITY, f32::INFINITY]),
+        max: const_vec3!([f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY]),
+    };
+
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Aabb { min, max }
+    }
+
+    /// The smallest box containing all points
+    pub fn from_points(points: &[Vec3]) -> Self {
+        points.iter().fold(Aabb::EMPTY, |aabb, &point| Aabb {
+            min: aabb.min.min(point),
+            max: aabb.max.max(point),
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Half the size of the box along each axis
+    pub fn extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /// The box enclosing this box after it's transformed by the matrix
+    pub fn transform(&self, matrix: &Mat4) -> Aabb {
+        if self.is_empty() {
+            return *self;
+        }
+        // Each axis of the matrix stretches the extents by its absolute value (Arvo, 1990)
+        let center = matrix.transform_point3(self.center());
+        let e = self.extents();
+        let extents = matrix.x_axis.truncate().abs() * e.x
+            + matrix.y_axis.truncate().abs() * e.y
+            + matrix.z_axis.truncate().abs() * e.z;
+        Aabb {
+            min: center - extents,
+            max: center + extents,
+        }
+    }
+}
+
+// ==================================== Frustum ===================================================
+
+/// View frustum as 6 planes with normals pointing inside
+#[derive(Debug, Clone, Copy)]
+pub struct Frustum {
+    /// (normal, distance): a point p is inside if normal.dot(p) + distance >= 0
+    planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extract the planes from a projection * view matrix (Gribb & Hartmann).
+    /// Expects OpenGL clip space where -w <= z <= w
+    pub fn from_matrix(view_projection: &Mat4) -> Self {
+        let m = view_projection;
+        let (r0, r1, r2, r3) = (m.row(0), m.row(1), m.row(2), m.row(3));
+        let planes = [
+            r3 + r0, // left
+            r3 - r0, // right
+            r3 + r1, // bottom
+            r3 - r1, // top
+            r3 + r2, // near
+            r3 - r2, // far
+        ];
+        Frustum {
+            planes: planes.map(|plane| {
+                let length = plane.truncate().length();
+                if length > f32::EPSILON {
+                    plane / length
+                } else {
+                    Vec4::W // the far plane of an infinite projection lets everything through
+                }
+            }),
+        }
+    }
+
+    /// False only if the box is fully outside one of the planes.
+    /// May return true for some boxes near the corners which are actually outside
+    pub fn intersects(&self, aabb: &Aabb) -> bool {
+        if aabb.is_empty() {
+            return false;
+        }
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane normal
+            let corner = Vec3::new(
+                if plane.x >= 0.0 {
+                    aabb.max.x
+                } else {
+                    aabb.min.x
+                },
+                if plane.y >= 0.0 {
+                    aabb.max.y
+                } else {
+                    aabb.min.y
+                },
+                if plane.z >= 0.0 {
+                    aabb.max.z
+                } else {
+                    aabb.min.z
+                },
+            );
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+// ==================================== Ray =======================================================
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// Not necessarily normalized, hit distances are in multiples of its length
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Ray { origin, direction }
+    }
+
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+
+    /// The same ray in another space. The direction isn't renormalized,
+    /// so hit distances along the transformed ray stay the same
+    pub fn transform(&self, matrix: &Mat4) -> Ray {
+        Ray {
+            origin: matrix.transform_point3(self.origin),
+            direction: matrix.transform_vector3(self.direction),
+        }
+    }
+
+    /// Distance to where the ray enters the box (0 if it starts inside), slab method
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let inverse_direction = self.direction.recip();
+        let t0 = (aabb.min - self.origin) * inverse_direction;
+        let t1 = (aabb.max - self.origin) * inverse_direction;
+        let t_enter = t0.min(t1).max_element().max(0.0);
+        let t_exit = t0.max(t1).min_element();
+        if t_enter <= t_exit {
+            Some(t_enter)
+        } else {
+            None
+        }
+    }
+
+    /// Distance to the triangle, Möller-Trumbore. Both sides of the triangle are hit
+    pub fn intersect_triangle(&self, triangle: &[Vec3; 3]) -> Option<f32> {
+        const EPSILON: f32 = 1e-7;
+        let [a, b, c] = *triangle;
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = self.direction.cross(edge2);
+        let determinant = edge1.dot(p);
+        if determinant.abs() < EPSILON {
+            return None; // parallel to the triangle
+        }
+        let inverse_determinant = 1.0 / determinant;
+        let s = self.origin - a;
+        let u = s.dot(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(edge1);
+        let v = self.direction.dot(q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = edge2.dot(q) * inverse_determinant;
+        if t > EPSILON {
+            Some(t)
+        } else {
+            None
+        }
+    }
+}
+
+// ==================================== Bvh =======================================================
+
+/// Triangles in a leaf before it's split
+const BVH_LEAF_SIZE: usize = 4;
+
+/// Bounding volume hierarchy over a triangle mesh, used for ray casts on the CPU
+#[derive(Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    triangles: Vec<[Vec3; 3]>,
+    /// Original index of each triangle, `triangles` is reordered while building
+    triangle_ids: Vec<usize>,
+}
+
+#[derive(Debug)]
+struct BvhNode {
+    bounds: Aabb,
+    /// Leaves hold `triangles[start..start + count]`,
+    /// inner nodes have count 0 and children at `start` and `start + 1`
+    start: usize,
+    count: usize,
+}
+
+impl Bvh {
+    pub fn new(triangles: Vec<[Vec3; 3]>) -> Self {
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            triangle_ids: (0..triangles.len()).collect(),
+            triangles,
+        };
+        if !bvh.triangles.is_empty() {
+            bvh.nodes.push(BvhNode {
+                bounds: Aabb::EMPTY,
+                start: 0,
+                count: bvh.triangles.len(),
+            });
+            bvh.subdivide(0);
+        }
+        bvh
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+
+    /// Split the node in half along the longest axis of its triangle centroids
+    fn subdivide(&mut self, node_index: usize) {
+        let (start, count) = (self.nodes[node_index].start, self.nodes[node_index].count);
+        let range = start..start + count;
+        self.nodes[node_index].bounds =
+            Aabb::from_points(self.triangles[range.clone()].concat().as_slice());
+        if count <= BVH_LEAF_SIZE {
+            return;
+        }
+
+        let centroid = |t: &[Vec3; 3]| (t[0] + t[1] + t[2]) / 3.0;
+        let centroids: Vec<Vec3> = self.triangles[range.clone()].iter().map(centroid).collect();
+        let extents = Aabb::from_points(&centroids).extents();
+        let axis = if extents.x >= extents.y && extents.x >= extents.z {
+            0
+        } else if extents.y >= extents.z {
+            1
+        } else {
+            2
+        };
+
+        // Sort triangles and their ids together by centroid along the axis
+        let mut order: Vec<usize> = (0..count).collect();
+        order.sort_by(|&i, &j| centroids[i][axis].total_cmp(&centroids[j][axis]));
+        let triangles: Vec<[Vec3; 3]> = order.iter().map(|&i| self.triangles[start + i]).collect();
+        let ids: Vec<usize> = order
+            .iter()
+            .map(|&i| self.triangle_ids[start + i])
+            .collect();
+        self.triangles[range.clone()].copy_from_slice(&triangles);
+        self.triangle_ids[range].copy_from_slice(&ids);
+
+        let half = count / 2;
+        let left = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds: Aabb::EMPTY,
+            start,
+            count: half,
+        });
+        self.nodes.push(BvhNode {
+            bounds: Aabb::EMPTY,
+            start: start + half,
+            count: count - half,
+        });
+        self.nodes[node_index].start = left;
+        self.nodes[node_index].count = 0;
+        self.subdivide(left);
+        self.subdivide(left + 1);
+    }
+
+    /// The closest triangle hit by the ray: (original triangle index, distance)
+    pub fn raycast(&self, ray: &Ray) -> Option<(usize, f32)> {
+        let mut closest: Option<(usize, f32)> = None;
+        if self.nodes.is_empty() {
+            return closest;
+        }
+        let mut stack = vec![0];
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            match ray.intersect_aabb(&node.bounds) {
+                Some(t) if closest.is_none_or(|(_, closest_t)| t < closest_t) => {}
+                _ => continue,
+            }
+            if node.count == 0 {
+                stack.push(node.start);
+                stack.push(node.start + 1);
+                continue;
+            }
+            for i in node.start..node.start + node.count {
+                if let Some(t) = ray.intersect_triangle(&self.triangles[i]) {
+                    if closest.is_none_or(|(_, closest_t)| t < closest_t) {
+                        closest = Some((self.triangle_ids[i], t));
+                    }
+                }
+            }
+        }
+        closest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn unit_box() -> Aabb {
+        Aabb::new(Vec3::splat(-1.0), Vec3::ONE)
+    }
+
+    #[test]
+    fn transformed_box_encloses_rotated_corners() {
+        let matrix = Mat4::from_translation(Vec3::new(5.0, 0.0, 0.0))
+            * Mat4::from_rotation_z(45f32.to_radians());
+        let aabb = unit_box().transform(&matrix);
+        let extent = 2f32.sqrt();
+        assert!(aabb
+            .min
+            .abs_diff_eq(Vec3::new(5.0 - extent, -extent, -1.0), EPSILON));
+        assert!(aabb
+            .max
+            .abs_diff_eq(Vec3::new(5.0 + extent, extent, 1.0), EPSILON));
+
+        assert!(Aabb::EMPTY.transform(&matrix).is_empty());
+    }
+
+    #[test]
+    fn infinite_frustum_keeps_distant_boxes() {
+        let projection = Mat4::perspective_infinite_rh(60f32.to_radians(), 1.0, 0.5);
+        let frustum = Frustum::from_matrix(&projection); // looking down -z from the origin
+
+        let far_away = Aabb::new(Vec3::new(-1.0, -1.0, -1e6), Vec3::new(1.0, 1.0, -1e6 + 1.0));
+        assert!(frustum.intersects(&far_away));
+        let across_near_plane = Aabb::new(Vec3::new(-0.1, -0.1, -1.0), Vec3::new(0.1, 0.1, -0.1));
+        assert!(frustum.intersects(&across_near_plane));
+
+        let behind_camera = Aabb::new(Vec3::new(-1.0, -1.0, 1.0), Vec3::new(1.0, 1.0, 2.0));
+        assert!(!frustum.intersects(&behind_camera));
+        let before_near_plane = Aabb::new(Vec3::splat(-0.1), Vec3::new(0.1, 0.1, -0.4));
+        assert!(!frustum.intersects(&before_near_plane));
+        let to_the_side = Aabb::new(Vec3::new(10.0, -1.0, -3.0), Vec3::new(12.0, 1.0, -2.0));
+        assert!(!frustum.intersects(&to_the_side));
+        assert!(!frustum.intersects(&Aabb::EMPTY));
+    }
+
+    #[test]
+    fn ray_hits_and_misses_box() {
+        let aabb = unit_box();
+        let ray = Ray::new(Vec3::new(-3.0, 0.5, 0.2), Vec3::new(2.0, 0.0, 0.0));
+        let t = ray.intersect_aabb(&aabb).unwrap();
+        assert!((t - 1.0).abs() < EPSILON); // distances are in multiples of the direction
+        assert!(ray.at(t).abs_diff_eq(Vec3::new(-1.0, 0.5, 0.2), EPSILON));
+
+        let diagonal = Ray::new(Vec3::splat(5.0), Vec3::splat(-1.0));
+        assert!((diagonal.intersect_aabb(&aabb).unwrap() - 4.0).abs() < EPSILON);
+
+        let pointing_away = Ray::new(Vec3::new(-3.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert_eq!(pointing_away.intersect_aabb(&aabb), None);
+        let passing_by = Ray::new(Vec3::new(-3.0, 0.0, 0.0), Vec3::new(1.0, 2.0, 0.0));
+        assert_eq!(passing_by.intersect_aabb(&aabb), None);
+    }
+
+    #[test]
+    fn ray_starting_inside_box_hits_at_zero() {
+        let ray = Ray::new(Vec3::new(0.5, -0.5, 0.0), Vec3::new(0.3, 1.0, -0.2));
+        assert_eq!(ray.intersect_aabb(&unit_box()), Some(0.0));
+    }
+
+    #[test]
+    fn ray_parallel_to_axes() {
+        // Zero direction components give infinite slab distances
+        let aabb = unit_box();
+        let inside_slabs = Ray::new(Vec3::new(0.5, 0.5, -4.0), Vec3::Z);
+        assert_eq!(inside_slabs.intersect_aabb(&aabb), Some(3.0));
+        let outside_slabs = Ray::new(Vec3::new(0.5, 1.5, -4.0), Vec3::Z);
+        assert_eq!(outside_slabs.intersect_aabb(&aabb), None);
+    }
+
+    #[test]
+    fn ray_hits_triangle_from_both_sides() {
+        let triangle = [
+            Vec3::new(-1.0, -1.0, 0.0),
+            Vec3::new(1.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        let front = Ray::new(Vec3::new(0.0, 0.0, 2.0), -Vec3::Z);
+        assert!((front.intersect_triangle(&triangle).unwrap() - 2.0).abs() < EPSILON);
+        let back = Ray::new(Vec3::new(0.0, 0.0, -3.0), Vec3::Z);
+        assert!((back.intersect_triangle(&triangle).unwrap() - 3.0).abs() < EPSILON);
+
+        let outside = Ray::new(Vec3::new(0.9, 0.9, 2.0), -Vec3::Z);
+        assert_eq!(outside.intersect_triangle(&triangle), None);
+        let parallel = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::X);
+        assert_eq!(parallel.intersect_triangle(&triangle), None);
+        let behind = Ray::new(Vec3::new(0.0, 0.0, 2.0), Vec3::Z);
+        assert_eq!(behind.intersect_triangle(&triangle), None);
+    }
+
+    /// Triangles facing +z at shuffled depths, so sorting them moves every id
+    fn stacked_triangles(count: usize) -> Vec<[Vec3; 3]> {
+        (0..count)
+            .map(|i| {
+                let z = -(((i * 7) % count) as f32) - 1.0;
+                [
+                    Vec3::new(-1.0, -1.0, z),
+                    Vec3::new(1.0, -1.0, z),
+                    Vec3::new(0.0, 1.0, z),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bvh_returns_closest_hit_with_original_index() {
+        let triangles = stacked_triangles(32);
+        let bvh = Bvh::new(triangles.clone());
+        assert!(bvh.nodes.len() > 1, "should be split into several leaves");
+
+        // The nearest triangle is z = -1, which was generated first
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 1.0), -Vec3::Z);
+        assert_eq!(bvh.raycast(&ray), Some((0, 2.0)));
+
+        // From the other end the nearest one is the deepest
+        let ray = Ray::new(Vec3::new(0.0, 0.0, -100.0), Vec3::Z);
+        let (id, t) = bvh.raycast(&ray).unwrap();
+        let deepest = (0..triangles.len())
+            .min_by(|&a, &b| triangles[a][0].z.total_cmp(&triangles[b][0].z))
+            .unwrap();
+        assert_eq!(id, deepest);
+        assert!((t - (100.0 + triangles[deepest][0].z)).abs() < EPSILON);
+
+        let miss = Ray::new(Vec3::new(5.0, 0.0, 1.0), -Vec3::Z);
+        assert_eq!(bvh.raycast(&miss), None);
+    }
+
+    #[test]
+    fn empty_bvh_hits_nothing() {
+        let bvh = Bvh::new(Vec::new());
+        assert!(bvh.is_empty());
+        assert_eq!(bvh.raycast(&Ray::new(Vec3::ZERO, Vec3::X)), None);
+    }
+}
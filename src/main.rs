@@ -4,51 +4,69 @@ extern crate gl as opengl;
 
 mod utils;
 
+mod animation;
 mod buffers;
 mod camera;
+mod geometry;
 mod scene;
 mod shader;
 mod skybox;
 mod texture;
+mod uniform_blocks;
 
 // ==================================== Imports ===================================================
 
 use gl::types::*;
 use std::error::Error;
 use std::ffi::CStr;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use glutin::event::{
     DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent,
 };
 use glutin::event_loop::{ControlFlow, EventLoop};
 use glutin::window::{Fullscreen, WindowBuilder};
-use glutin::{Api, GlProfile, GlRequest};
+use glutin::{GlProfile, GlRequest};
 use glutin::{PossiblyCurrent, WindowedContext};
 
 use glam::{Vec3, Vec4};
 
 // Local imports
+use buffers::{Buffer, BufferUsage, RingBuffer};
 use camera::Camera;
 use camera::Movement::*;
-use scene::Scene;
-use shader::Program;
+use scene::{Scene, SceneShaders, Shading};
 use skybox::Skybox;
+use uniform_blocks::{CameraBlock, LightsBlock, MaterialDefaultsBlock, UniformBlock};
+use utils::report_gl_leaks;
+
+/// How often shader sources are checked for changes
+const SHADER_RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Bytes of streamed data (instance transforms, indirect commands) available each frame
+const STREAM_FRAME_SIZE: usize = 4 * 1024 * 1024;
 
 // ==================================== Types =====================================================
 
 struct Game {
-    windowed_context: WindowedContext<PossiblyCurrent>,
     input: Input,
     camera: Camera,
     in_focus: bool,
     frame_start: Instant,
+    last_shader_check: Instant,
 
     // @tmp
     scene: Scene,
-    shader: Program,
+    shaders: SceneShaders,
     skybox: Skybox,
     light: DirectionalLight,
+    /// Per-frame data for draws
+    stream: RingBuffer,
+    /// Bound to the MaterialDefaults uniform block
+    _material_defaults: Buffer<MaterialDefaultsBlock>,
+
+    // Fields are dropped in order, GL objects above must go while the context is alive
+    windowed_context: WindowedContext<PossiblyCurrent>,
 }
 
 #[derive(Default)]
@@ -60,28 +78,69 @@ struct Input {
 }
 
 struct DirectionalLight {
-    color: Vec3,
     direction: Vec3,
+
+    ambient: Vec3,
+    diffuse: Vec3,
+    specular: Vec3,
+    /// Color for the PBR shaders
+    radiance: Vec3,
 }
 
 // ==================================== Functions =================================================
 
 fn main() {
+    // Check glTF files without opening a window: game2 --validate <path>...
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() > 1 && args[1] == "--validate" {
+        std::process::exit(validate(&args[2..]));
+    }
+
     let event_loop = EventLoop::new();
-    let mut game = Game::new(&event_loop).unwrap_or_else(|error| {
+    let game = Game::new(&event_loop).unwrap_or_else(|error| {
         eprintln!("{}", error);
         std::process::exit(1);
     });
+    let mut game = Some(game);
 
     // Run winit event loop (which is used as the game loop)
     event_loop.run(move |event, _, control_flow| {
-        if let Err(error) = game.main_loop(event, control_flow) {
-            eprint!("{}", error);
-            std::process::exit(1);
-        };
+        if let Event::LoopDestroyed = event {
+            // run() never returns, so drop the game here to delete its GL objects
+            drop(game.take());
+            report_gl_leaks();
+            return;
+        }
+        if let Some(game) = game.as_mut() {
+            if let Err(error) = game.main_loop(event, control_flow) {
+                eprint!("{}", error);
+                std::process::exit(1);
+            };
+        }
     });
 }
 
+/// Prints problems found in the files and returns the process exit code
+fn validate(paths: &[String]) -> i32 {
+    let mut exit_code = 0;
+    for path in paths {
+        match Scene::validate(path) {
+            Ok(errors) if errors.is_empty() => println!("{}: OK", path),
+            Ok(errors) => {
+                for error in errors {
+                    println!("{}: {}", path, error);
+                }
+                exit_code = 1;
+            }
+            Err(error) => {
+                println!("{}: {}", path, error);
+                exit_code = 1;
+            }
+        }
+    }
+    exit_code
+}
+
 impl Game {
     /// Creates a window and inits a new game
     fn new(event_loop: &EventLoop<()>) -> Result<Self, Box<dyn Error>> {
@@ -91,7 +150,8 @@ impl Game {
             .with_resizable(false)
             .with_fullscreen(Some(Fullscreen::Borderless(event_loop.primary_monitor())))
             .with_inner_size(glutin::dpi::LogicalSize::new(1366.0, 768.0));
-        let gl_request = GlRequest::Specific(Api::OpenGl, (3, 3));
+        // 3.3 is enough, newer contexts enable GPU-driven draws
+        let gl_request = GlRequest::Latest;
         let gl_profile = GlProfile::Core;
         let windowed_context = glutin::ContextBuilder::new()
             .with_gl(gl_request)
@@ -99,7 +159,7 @@ impl Game {
             .with_double_buffer(Some(true))
             .with_depth_buffer(16)
             .with_vsync(true)
-            .build_windowed(window_builder, &event_loop)?;
+            .build_windowed(window_builder, event_loop)?;
 
         // Set up OpenGL
         let windowed_context = unsafe { windowed_context.make_current().unwrap() };
@@ -130,28 +190,35 @@ impl Game {
             window_size.height,
         );
 
+        let light_color = Vec3::new(1.0, 0.7, 0.7);
         let light = DirectionalLight {
-            color: Vec3::new(1.0, 0.7, 0.7),
             direction: Vec3::new(0.37f32, -0.56, 0.75),
+            ambient: 0.2f32 * light_color,
+            diffuse: 0.5f32 * light_color,
+            specular: 1.0f32 * light_color,
+            radiance: 2.0f32 * light_color,
         };
 
-        // Flat color shader
-        let shader = Program::new()
-            .vertex_shader("assets/shaders/flatcolor/flatcolor.vert")?
-            .fragment_shader("assets/shaders/flatcolor/flatcolor.frag")?
-            .link()?;
-        shader.set_used();
+        let shaders = SceneShaders::new()?;
 
-        // Directional light
-        shader.set_vec3("directional_light.ambient", &(0.2f32 * light.color))?;
-        shader.set_vec3("directional_light.diffuse", &(0.5f32 * light.color))?;
-        shader.set_vec3("directional_light.specular", &(1.0f32 * light.color))?;
-
-        // Set default material
-        shader.set_vec3("material.specular", &Vec3::new(0.4, 0.4, 0.4))?;
-        shader.set_float("material.shininess", 10.0)?;
+        let material_defaults = Buffer::new(
+            &[MaterialDefaultsBlock {
+                specular: [0.4, 0.4, 0.4],
+                shininess: 10.0,
+            }],
+            BufferUsage::Static,
+        );
+        material_defaults.bind_as_uniform_buffer(MaterialDefaultsBlock::BINDING);
 
-        let scene = Scene::from("assets/models/culdesac/culdesac.glb")?;
+        let mut scene = Scene::from("assets/models/culdesac/culdesac.glb")?;
+        for warning in scene.warnings() {
+            eprintln!("Scene warning: {}", warning);
+        }
+        scene.set_shading(Shading::Phong); // culdesac is authored with vertex colors only
+        let first_clip = scene.animator().clip_names().next().map(str::to_owned);
+        if let Some(clip) = first_clip {
+            scene.animator().play(&clip)?;
+        }
         let skybox = Skybox::from([
             "assets/textures/skybox/right.jpg",
             "assets/textures/skybox/left.jpg",
@@ -162,16 +229,20 @@ impl Game {
         ])?;
 
         Ok(Game {
-            windowed_context,
             input: Input::default(),
             camera,
             in_focus: true,
             frame_start: Instant::now(),
+            last_shader_check: Instant::now(),
 
             scene,
-            shader,
+            shaders,
             skybox,
             light,
+            stream: RingBuffer::new(STREAM_FRAME_SIZE),
+            _material_defaults: material_defaults,
+
+            windowed_context,
         })
     }
 
@@ -188,9 +259,24 @@ impl Game {
                     state: ElementState::Pressed,
                     ..
                 } => {
-                    // Mouse button click
-
-                    println!("camera: {:?}", self.camera);
+                    // Mouse button click: select what's under the crosshair (the cursor is grabbed)
+                    let ray = self.camera.screen_ray(self.camera.screen_center());
+                    match self.scene.raycast(&ray) {
+                        Some(hit) => {
+                            let name = self.scene.node_name(hit.node_id).unwrap_or("<unnamed>");
+                            println!(
+                                "Selected node {} ({}), primitive {}, triangle {} at {} ({:.2} m)",
+                                hit.node_id,
+                                name,
+                                hit.primitive_index,
+                                hit.triangle,
+                                hit.position,
+                                hit.distance
+                            );
+                            self.scene.select(Some(hit.node_id));
+                        }
+                        None => self.scene.select(None),
+                    }
                 }
                 WindowEvent::Focused(focused) => {
                     self.in_focus = focused;
@@ -210,6 +296,9 @@ impl Game {
                         VirtualKeyCode::A => self.input.left = state == ElementState::Pressed,
                         VirtualKeyCode::S => self.input.back = state == ElementState::Pressed,
                         VirtualKeyCode::D => self.input.right = state == ElementState::Pressed,
+                        VirtualKeyCode::P if state == ElementState::Pressed => {
+                            self.toggle_animation()?
+                        }
                         VirtualKeyCode::Escape => *control_flow = ControlFlow::Exit,
                         _ => {}
                     }
@@ -249,6 +338,13 @@ impl Game {
             self.camera.go(Right, delta_time);
         }
 
+        self.scene.update(delta_time);
+
+        if now.duration_since(self.last_shader_check) > SHADER_RELOAD_INTERVAL {
+            self.last_shader_check = now;
+            self.reload_shaders();
+        }
+
         let proj = self.camera.get_projection_matrix();
         let view = self.camera.get_view_matrix();
 
@@ -256,9 +352,6 @@ impl Game {
         unsafe {
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
         }
-        self.shader.set_used();
-        self.shader.set_mat4("proj", &proj)?;
-        self.shader.set_mat4("view", &view)?;
         let light_direction: Vec3 = (view
             * Vec4::new(
                 self.light.direction.x,
@@ -267,10 +360,34 @@ impl Game {
                 0.0,
             ))
         .into();
-        self.shader
-            .set_vec3("directional_light.direction", &light_direction)?;
-        self.scene.draw(&self.shader)?;
-        self.skybox.draw(&proj, &view)?; // draw skybox last
+        self.stream.begin_frame();
+        let alignment = self.stream.uniform_alignment();
+        let camera = self
+            .stream
+            .push(&[CameraBlock { proj, view }], alignment)
+            .ok_or("Stream buffer is full")?;
+        self.stream
+            .bind_as_uniform_buffer(CameraBlock::BINDING, camera);
+        let lights = self
+            .stream
+            .push(
+                &[LightsBlock::new(
+                    light_direction,
+                    self.light.ambient,
+                    self.light.diffuse,
+                    self.light.specular,
+                    self.light.radiance,
+                )],
+                alignment,
+            )
+            .ok_or("Stream buffer is full")?;
+        self.stream
+            .bind_as_uniform_buffer(LightsBlock::BINDING, lights);
+
+        self.scene
+            .draw(&self.shaders, &mut self.stream, &proj, &view)?;
+        self.skybox.draw()?; // draw skybox last
+        self.stream.end_frame();
 
         self.windowed_context.swap_buffers()?;
 
@@ -284,16 +401,44 @@ impl Game {
 
         Ok(())
     }
+
+    /// Pause or resume the first animation clip of the scene
+    fn toggle_animation(&mut self) -> Result<(), Box<dyn Error>> {
+        let animator = self.scene.animator();
+        let first_clip = animator.clip_names().next().map(str::to_owned);
+        if let Some(clip) = first_clip {
+            if animator.is_playing(&clip) {
+                animator.pause(&clip)?;
+            } else {
+                animator.resume(&clip)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuild programs whose shader sources changed. A program which fails to build
+    /// is reported and the previous one stays in use
+    fn reload_shaders(&mut self) {
+        let mut programs = self.shaders.all_mut();
+        programs.push(self.skybox.shader_mut());
+        for program in programs {
+            match program.reload_if_changed() {
+                Ok(true) => println!("Reloaded {}", program.source_paths().join(", ")),
+                Ok(false) => {}
+                Err(error) => eprintln!("{}", error),
+            }
+        }
+    }
 }
 
 extern "system" fn debug_callback(
-    source: GLenum,
+    _source: GLenum,
     gltype: GLenum,
-    id: GLuint,
-    severity: GLenum,
-    length: GLsizei,
+    _id: GLuint,
+    _severity: GLenum,
+    _length: GLsizei,
     message: *const GLchar,
-    user_param: *mut std::os::raw::c_void,
+    _user_param: *mut std::os::raw::c_void,
 ) {
     let msg_type = if gltype == gl::DEBUG_TYPE_ERROR {
         "** GL ERROR ** "
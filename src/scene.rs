@@ -1,13 +1,42 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::rc::Rc;
+
+use bytemuck::{Pod, Zeroable};
+use gl_derive::{Uniforms, Vertex};
 use thiserror::Error;
 
 use gl::types::*;
-use glam::Mat4;
-use gltf::accessor::DataType;
+use glam::{const_vec3, Mat4, Quat, Vec3, Vec4};
+use gltf::accessor::{DataType, Dimensions};
 use gltf::Semantic::*;
 
-use crate::buffers::{Buffer, ElementBuffer, VertexArray};
+use crate::animation::{AnimationError, Animator, Transform};
+use crate::buffers::{
+    Buffer, BufferUsage, ElementBuffer, RingBuffer, RingSlice, Vertex, VertexArray,
+    VertexAttribute, VertexLayout,
+};
+use crate::geometry::{Aabb, Bvh, Frustum, Ray};
 use crate::shader::{Program, ShaderError};
-use crate::utils::gl_check_error;
+use crate::texture::Texture;
+use crate::utils::{gl_check_error, gl_supports_indirect_draws};
+
+// Texture units used by scene materials
+const BASE_COLOR_UNIT: i32 = 0;
+const METALLIC_ROUGHNESS_UNIT: i32 = 1;
+const NORMAL_UNIT: i32 = 2;
+const OCCLUSION_UNIT: i32 = 3;
+const EMISSIVE_UNIT: i32 = 4;
+
+/// Size of the joint_matrices uniform, defined as MAX_JOINTS in the vertex shaders
+const MAX_JOINTS: usize = 64;
+
+/// Added to the color of the selected node
+const HIGHLIGHT_COLOR: Vec3 = const_vec3!([0.25, 0.2, 0.0]);
+
+/// Extensions a file may require and still be displayed correctly
+const SUPPORTED_EXTENSIONS: &[&str] = &["KHR_mesh_quantization"];
 
 // ==================================== Error =====================================================
 
@@ -18,87 +47,1041 @@ pub enum SceneError {
 
     #[error("Shader error when drawing scene: {0}")]
     ShaderError(#[from] ShaderError),
+
+    #[error("Animation error: {0}")]
+    AnimationError(#[from] AnimationError),
+
+    #[error("Unsupported {semantic} attribute: {dimensions:?} of {data_type:?} (normalized: {normalized})")]
+    UnsupportedAttribute {
+        semantic: String,
+        dimensions: Dimensions,
+        data_type: DataType,
+        normalized: bool,
+    },
+
+    #[error("Required extension {0} is not supported")]
+    UnsupportedExtension(String),
+
+    #[error("The file contains no scenes")]
+    NoScene,
+
+    #[error("Primitive {primitive} of mesh {mesh} has no positions")]
+    MissingPositions { mesh: usize, primitive: usize },
+
+    #[error(
+        "Skin {skin} has {count} joints, more than {} supported. Its meshes are drawn in bind pose",
+        MAX_JOINTS
+    )]
+    TooManyJoints { skin: usize, count: usize },
+
+    #[error("Material {material} uses TEXCOORD_{set}, only TEXCOORD_0 is supported")]
+    UnsupportedTexCoord { material: usize, set: u32 },
+
+    #[error("Morph target {target} of primitive {primitive} in mesh {mesh} has unreadable {semantic} deltas")]
+    InvalidMorphTarget {
+        mesh: usize,
+        primitive: usize,
+        target: usize,
+        semantic: String,
+    },
+
+    #[error("Node {node} can't be a child of node {parent}, it would create a cycle")]
+    InvalidParent { node: usize, parent: usize },
+
+    #[error("Stream buffer is out of space ({size} bytes per frame)")]
+    StreamBufferFull { size: usize },
 }
 
 // ==================================== Scene =====================================================
 
+/// Which lighting model a material is drawn with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shading {
+    /// Blinn/Phong with vertex colors, drawn with the flatcolor shader
+    Phong,
+    /// glTF metallic-roughness (Cook-Torrance), drawn with the pbr shader
+    Pbr,
+}
+
+/// Programs the scene is drawn with: one per shading model and vertex type
+pub struct SceneShaders {
+    pub phong: Program,
+    pub phong_skinned: Program,
+    pub phong_instanced: Program,
+    pub pbr: Program,
+    pub pbr_skinned: Program,
+    pub pbr_instanced: Program,
+
+    /// Programs for multi-draw indirect, None on contexts which don't support it
+    pub phong_indirect: Option<Program>,
+    pub pbr_indirect: Option<Program>,
+}
+
+impl SceneShaders {
+    pub fn new() -> Result<Self, ShaderError> {
+        let (phong_indirect, pbr_indirect) = if gl_supports_indirect_draws() {
+            (
+                Some(Self::variant(Shading::Phong, "INDIRECT")?),
+                Some(Self::variant(Shading::Pbr, "INDIRECT")?),
+            )
+        } else {
+            (None, None)
+        };
+
+        Ok(SceneShaders {
+            phong_indirect,
+            pbr_indirect,
+            phong: Self::variant(Shading::Phong, "")?,
+            phong_skinned: Self::variant(Shading::Phong, "SKINNED")?,
+            phong_instanced: Self::variant(Shading::Phong, "INSTANCED")?,
+            pbr: Self::variant(Shading::Pbr, "")?,
+            pbr_skinned: Self::variant(Shading::Pbr, "SKINNED")?,
+            pbr_instanced: Self::variant(Shading::Pbr, "INSTANCED")?,
+        })
+    }
+
+    /// Builds the program for a shading model with the vertex shader variant
+    /// selected by `feature`: "SKINNED", "INSTANCED", "INDIRECT" or "" for the plain one
+    fn variant(shading: Shading, feature: &str) -> Result<Program, ShaderError> {
+        let (vertex, fragment) = match shading {
+            Shading::Phong => (
+                "assets/shaders/flatcolor/flatcolor.vert",
+                "assets/shaders/flatcolor/flatcolor.frag",
+            ),
+            Shading::Pbr => ("assets/shaders/pbr/pbr.vert", "assets/shaders/pbr/pbr.frag"),
+        };
+        let mut program = Program::new().define_value("MAX_JOINTS", MAX_JOINTS);
+        if !feature.is_empty() {
+            program = program.define(feature);
+        }
+        if feature == "INDIRECT" {
+            // SSBOs and the shader draw parameters extension
+            program = program.version("430 core");
+        }
+        program
+            .vertex_shader(vertex)?
+            .fragment_shader(fragment)?
+            .link()
+    }
+
+    pub fn get(&self, shading: Shading, skinned: bool) -> &Program {
+        match (shading, skinned) {
+            (Shading::Phong, false) => &self.phong,
+            (Shading::Phong, true) => &self.phong_skinned,
+            (Shading::Pbr, false) => &self.pbr,
+            (Shading::Pbr, true) => &self.pbr_skinned,
+        }
+    }
+
+    /// Program taking model matrices per instance
+    pub fn instanced(&self, shading: Shading) -> &Program {
+        match shading {
+            Shading::Phong => &self.phong_instanced,
+            Shading::Pbr => &self.pbr_instanced,
+        }
+    }
+
+    /// Program taking model matrices from the indirect draw transforms
+    pub fn indirect(&self, shading: Shading) -> Option<&Program> {
+        match shading {
+            Shading::Phong => self.phong_indirect.as_ref(),
+            Shading::Pbr => self.pbr_indirect.as_ref(),
+        }
+    }
+
+    pub fn all_mut(&mut self) -> Vec<&mut Program> {
+        let mut programs = vec![
+            &mut self.phong,
+            &mut self.phong_skinned,
+            &mut self.phong_instanced,
+            &mut self.pbr,
+            &mut self.pbr_skinned,
+            &mut self.pbr_instanced,
+        ];
+        programs.extend(self.phong_indirect.as_mut());
+        programs.extend(self.pbr_indirect.as_mut());
+        programs
+    }
+}
+
+/// Number of primitives drawn and skipped by culling in a frame
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DrawStats {
+    pub drawn: usize,
+    pub culled: usize,
+    /// Instanced draws count once for all their instances
+    pub draw_calls: usize,
+}
+
+/// The closest triangle hit by a ray
+#[derive(Debug, Clone, Copy)]
+pub struct RayHit {
+    pub node_id: usize,
+    /// Index of the primitive in the node's mesh
+    pub primitive_index: usize,
+    /// Index of the triangle in the primitive, in the order of its indices
+    pub triangle: usize,
+    pub position: Vec3,
+    /// Distance from the ray origin in multiples of the ray direction length
+    pub distance: f32,
+}
+
 pub struct Scene {
     nodes: Vec<Node>,
-    meshes: Vec<Mesh>, // @optimisation: make a flat array of primitives instead
+    root_ids: Vec<usize>,
+    animator: Animator,
+    meshes: Vec<Mesh>,
+    /// Primitives of all meshes
+    primitives: Vec<Primitive>,
+    /// Vertex data of all primitives except morphed ones
+    arenas: Vec<VertexArena>,
+    skins: Vec<Skin>,
+    materials: Vec<Material>,
+    textures: Vec<Texture>,
+
+    /// Used for primitives which don't specify a material
+    default_material: Material,
+    /// Bound in place of textures a material doesn't have
+    default_textures: DefaultTextures,
+
+    /// Unsupported content found while loading
+    warnings: Vec<SceneError>,
+
+    /// Node drawn highlighted
+    selected: Option<usize>,
 }
 
 impl Scene {
+    /// Load a glTF file. Fails only if the file can't be imported at all,
+    /// unsupported content is skipped where possible and reported in `warnings`
     pub fn from(path: &str) -> Result<Self, SceneError> {
-        let (document, buffer_data, _images) = gltf::import(path)?;
-
-        // Create OpenGL buffers
-        let buffers: Vec<Buffer> = buffer_data
-            .into_iter()
-            .map(|data| Buffer::create(data.as_ptr(), data.len()))
-            .collect();
+        let (document, buffer_data, images) = gltf::import(path)?;
+        let mut warnings = document_errors(&document);
 
-        // Create nodes
+        // Create nodes and link them to their parents
         let mut nodes: Vec<Node> = document.nodes().map(Node::from_gltf).collect();
+        for node_id in 0..nodes.len() {
+            for i in 0..nodes[node_id].children_ids.len() {
+                let child_id = nodes[node_id].children_ids[i];
+                nodes[child_id].parent_id = Some(node_id);
+            }
+        }
 
-        // Store final transforms in each node
-        let parent_transform = Mat4::IDENTITY;
-        let scene = document.default_scene().unwrap();
-        for gltf_node in scene.nodes() {
-            recursive_update_transforms(gltf_node, &mut nodes, &parent_transform);
+        let root_ids: Vec<usize> = match document.default_scene() {
+            Some(scene) => scene.nodes().map(|n| n.index()).collect(),
+            None => match document.scenes().next() {
+                Some(scene) => scene.nodes().map(|n| n.index()).collect(),
+                None => Vec::new(),
+            },
+        };
+        let mut animation_errors = Vec::new();
+        let animator = Animator::from_gltf(&document, &buffer_data, &mut animation_errors);
+        warnings.extend(animation_errors.into_iter().map(SceneError::from));
+
+        // Create skins
+        let skins: Vec<Skin> = document
+            .skins()
+            .map(|skin| Skin::from_gltf(skin, &buffer_data))
+            .collect();
+
+        // Joint indices past MAX_JOINTS can't be read in the shader, such skins are left
+        // out and their nodes drawn in bind pose (reported as TooManyJoints)
+        for node in nodes.iter_mut() {
+            if let Some(skin_id) = node.skin_id {
+                if skins[skin_id].joint_ids.len() > MAX_JOINTS {
+                    node.skin_id = None;
+                }
+            }
         }
 
-        // Create meshes
+        // Create meshes, gathering their vertices into a few large buffers
+        let mut primitives = Vec::new();
+        let mut arena_builder = ArenaBuilder::default();
         let meshes: Vec<Mesh> = document
             .meshes()
-            .map(|mesh| Mesh::from_gltf(mesh, &buffers))
+            .map(|mesh| {
+                Mesh::from_gltf(
+                    mesh,
+                    &buffer_data,
+                    &mut primitives,
+                    &mut arena_builder,
+                    &mut warnings,
+                )
+            })
+            .collect();
+        let arenas = arena_builder.build();
+
+        // Skinned nodes are deformed by their joints, so their bind pose bounds can't be used
+        for node in nodes.iter_mut() {
+            node.local_bounds = match (node.mesh_id, node.skin_id) {
+                (Some(mesh_id), None) => Some(
+                    primitives[meshes[mesh_id].primitive_ids.clone()]
+                        .iter()
+                        .fold(Aabb::EMPTY, |bounds, p| bounds.union(&p.bounds)),
+                ),
+                _ => None,
+            };
+        }
+
+        // Upload textures. Color textures are sampled as sRGB, the rest hold linear data
+        let srgb_textures: Vec<usize> = document
+            .materials()
+            .flat_map(|m| {
+                let base_color = m.pbr_metallic_roughness().base_color_texture();
+                let emissive = m.emissive_texture();
+                base_color.into_iter().chain(emissive)
+            })
+            .map(|info| info.texture().index())
             .collect();
+        let textures: Vec<Texture> = document
+            .textures()
+            .map(|texture| {
+                let image = &images[texture.source().index()];
+                let srgb = srgb_textures.contains(&texture.index());
+                Texture::new()
+                    .set_gltf_sampler(&texture.sampler())
+                    .set_gltf_image_2d(image, srgb)
+            })
+            .collect();
+
+        // Create materials
+        let materials: Vec<Material> = document.materials().map(Material::from_gltf).collect();
+
+        let mut scene = Scene {
+            nodes,
+            root_ids,
+            animator,
+            meshes,
+            primitives,
+            arenas,
+            skins,
+            materials,
+            textures,
+            default_material: Material::default(),
+            default_textures: DefaultTextures::new(),
+            warnings,
+            selected: None,
+        };
+        scene.update_transforms();
+        scene.update_joint_matrices();
+
+        Ok(scene)
+    }
+
+    /// Check a glTF file for content the renderer can't display.
+    /// Doesn't need a GL context, so it can run in asset checks
+    pub fn validate(path: &str) -> Result<Vec<SceneError>, SceneError> {
+        let (document, buffer_data, _) = gltf::import(path)?;
+        let mut errors = document_errors(&document);
+        let mut animation_errors = Vec::new();
+        Animator::from_gltf(&document, &buffer_data, &mut animation_errors);
+        errors.extend(animation_errors.into_iter().map(SceneError::from));
+        for mesh in document.meshes() {
+            for primitive in mesh.primitives() {
+                errors.extend(primitive_errors(&mesh, &primitive));
+            }
+        }
+        Ok(errors)
+    }
+
+    /// Unsupported content skipped while loading
+    pub fn warnings(&self) -> &[SceneError] {
+        &self.warnings
+    }
+
+    /// Play, pause and blend animation clips
+    pub fn animator(&mut self) -> &mut Animator {
+        &mut self.animator
+    }
+
+    /// Advance animations and update the transforms of nodes which have moved
+    pub fn update(&mut self, delta_time: f32) {
+        if !self.animator.is_empty() {
+            self.animator.advance(delta_time);
+
+            let mut poses: Vec<Transform> = self.nodes.iter().map(|n| n.local).collect();
+            let mut weights: Vec<Vec<f32>> = self
+                .nodes
+                .iter()
+                .map(|n| n.default_weights.clone())
+                .collect();
+            self.animator.apply(&mut poses, &mut weights);
+            for ((node, pose), weights) in self.nodes.iter_mut().zip(poses).zip(weights) {
+                if node.pose != pose {
+                    node.pose = pose;
+                    node.dirty = true;
+                }
+                node.weights = weights;
+            }
+        }
+
+        if self.update_transforms() {
+            self.update_joint_matrices();
+        }
+    }
+
+    /// Recompute world transforms of dirty nodes and their descendants.
+    /// Returns whether any transform has changed
+    fn update_transforms(&mut self) -> bool {
+        let mut changed = false;
+        for i in 0..self.root_ids.len() {
+            let root_id = self.root_ids[i];
+            changed |=
+                recursive_update_transforms(root_id, &mut self.nodes, &Mat4::IDENTITY, false);
+        }
+        changed
+    }
+
+    /// Recalculate joint matrices of skinned nodes from the current node transforms
+    fn update_joint_matrices(&mut self) {
+        for i in 0..self.nodes.len() {
+            let skin = match self.nodes[i].skin_id {
+                Some(skin_id) => &self.skins[skin_id],
+                None => continue,
+            };
+            // Joint matrices are relative to the skinned node, the model matrix is applied after
+            let inverse_node_transform = self.nodes[i].transform.inverse();
+            let joint_matrices = skin
+                .joint_ids
+                .iter()
+                .zip(skin.inverse_bind_matrices.iter())
+                .map(|(&joint_id, inverse_bind_matrix)| {
+                    inverse_node_transform * self.nodes[joint_id].transform * *inverse_bind_matrix
+                })
+                .collect();
+            self.nodes[i].joint_matrices = joint_matrices;
+        }
+    }
+
+    #[allow(dead_code)]
+    /// Id of the first node with the given name
+    pub fn find_node(&self, name: &str) -> Option<usize> {
+        self.nodes
+            .iter()
+            .position(|node| node.name.as_deref() == Some(name))
+    }
+
+    pub fn node_name(&self, node_id: usize) -> Option<&str> {
+        self.nodes[node_id].name.as_deref()
+    }
+
+    #[allow(dead_code)]
+    pub fn parent(&self, node_id: usize) -> Option<usize> {
+        self.nodes[node_id].parent_id
+    }
+
+    #[allow(dead_code)]
+    pub fn children(&self, node_id: usize) -> &[usize] {
+        &self.nodes[node_id].children_ids
+    }
+
+    #[allow(dead_code)]
+    /// Transform relative to the parent, not including animation
+    pub fn local_transform(&self, node_id: usize) -> Transform {
+        self.nodes[node_id].local
+    }
+
+    #[allow(dead_code)]
+    /// The final transform as of the last `update`
+    pub fn world_transform(&self, node_id: usize) -> Mat4 {
+        self.nodes[node_id].transform
+    }
+
+    #[allow(dead_code)]
+    pub fn set_local_transform(&mut self, node_id: usize, transform: Transform) {
+        let node = &mut self.nodes[node_id];
+        node.local = transform;
+        node.pose = transform;
+        node.dirty = true;
+    }
+
+    #[allow(dead_code)]
+    pub fn set_translation(&mut self, node_id: usize, translation: Vec3) {
+        let transform = Transform {
+            translation,
+            ..self.nodes[node_id].local
+        };
+        self.set_local_transform(node_id, transform);
+    }
+
+    #[allow(dead_code)]
+    pub fn set_rotation(&mut self, node_id: usize, rotation: Quat) {
+        let transform = Transform {
+            rotation,
+            ..self.nodes[node_id].local
+        };
+        self.set_local_transform(node_id, transform);
+    }
+
+    #[allow(dead_code)]
+    pub fn set_scale(&mut self, node_id: usize, scale: Vec3) {
+        let transform = Transform {
+            scale,
+            ..self.nodes[node_id].local
+        };
+        self.set_local_transform(node_id, transform);
+    }
+
+    #[allow(dead_code)]
+    /// Move the node under a new parent, or make it a root if `parent_id` is None.
+    /// The local transform is kept, so the node moves along with its new parent
+    pub fn reparent(&mut self, node_id: usize, parent_id: Option<usize>) -> Result<(), SceneError> {
+        // The new parent must not be the node itself or one of its descendants
+        let mut ancestor_id = parent_id;
+        while let Some(id) = ancestor_id {
+            if id == node_id {
+                return Err(SceneError::InvalidParent {
+                    node: node_id,
+                    parent: parent_id.unwrap(),
+                });
+            }
+            ancestor_id = self.nodes[id].parent_id;
+        }
+
+        match self.nodes[node_id].parent_id {
+            Some(old_parent_id) => self.nodes[old_parent_id]
+                .children_ids
+                .retain(|&id| id != node_id),
+            None => self.root_ids.retain(|&id| id != node_id),
+        }
+        match parent_id {
+            Some(parent_id) => self.nodes[parent_id].children_ids.push(node_id),
+            None => self.root_ids.push(node_id),
+        }
+        self.nodes[node_id].parent_id = parent_id;
+        self.nodes[node_id].dirty = true;
 
-        Ok(Scene { nodes, meshes })
+        Ok(())
     }
 
-    /// Draw all nodes in the scene
-    pub fn draw(&self, shader: &Program) -> Result<(), SceneError> {
-        for node in self.nodes.iter().filter(|n| n.mesh_id.is_some()) {
-            shader.set_mat4("model", &node.transform)?;
-            let mesh = &self.meshes[node.mesh_id.unwrap()];
-            for primitive in mesh.primitives.iter() {
-                primitive.draw();
+    /// The closest triangle hit by a world space ray.
+    /// Skinned and morphed meshes are tested in their rest pose
+    pub fn raycast(&self, ray: &Ray) -> Option<RayHit> {
+        let mut closest: Option<RayHit> = None;
+        for (node_id, node) in self.nodes.iter().enumerate() {
+            let mesh = match node.mesh_id {
+                Some(mesh_id) => &self.meshes[mesh_id],
+                None => continue,
+            };
+            if let Some(bounds) = node.bounds {
+                match ray.intersect_aabb(&bounds) {
+                    Some(t) if closest.is_none_or(|hit| t < hit.distance) => {}
+                    _ => continue,
+                }
+            }
+
+            // Distances along the local ray are the same as along the world one
+            let local_ray = ray.transform(&node.transform.inverse());
+            let mesh_primitives = &self.primitives[mesh.primitive_ids.clone()];
+            for (primitive_index, primitive) in mesh_primitives.iter().enumerate() {
+                if let Some((triangle, t)) = primitive.bvh.raycast(&local_ray) {
+                    if closest.is_none_or(|hit| t < hit.distance) {
+                        closest = Some(RayHit {
+                            node_id,
+                            primitive_index,
+                            triangle,
+                            position: ray.at(t),
+                            distance: t,
+                        });
+                    }
+                }
             }
         }
+        closest
+    }
+
+    /// Highlight a node, or nothing if None
+    pub fn select(&mut self, node_id: Option<usize>) {
+        self.selected = node_id;
+    }
+
+    #[allow(dead_code)]
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Use the same shading model for all materials
+    pub fn set_shading(&mut self, shading: Shading) {
+        self.default_material.shading = shading;
+        for material in self.materials.iter_mut() {
+            material.shading = shading;
+        }
+    }
+
+    #[allow(dead_code)]
+    /// Set the shading model for the material with the given name.
+    /// Returns false if there's no such material
+    pub fn set_material_shading(&mut self, name: &str, shading: Shading) -> bool {
+        let mut found = false;
+        for material in self.materials.iter_mut() {
+            if material.name.as_deref() == Some(name) {
+                material.shading = shading;
+                found = true;
+            }
+        }
+        found
+    }
+
+    /// Draw the nodes in the view frustum. The camera and lights uniform blocks should be bound,
+    /// per-frame data such as instance transforms goes to the stream buffer
+    pub fn draw(
+        &self,
+        shaders: &SceneShaders,
+        stream: &mut RingBuffer,
+        projection: &Mat4,
+        view: &Mat4,
+    ) -> Result<DrawStats, SceneError> {
+        let frustum = Frustum::from_matrix(&(*projection * *view));
+        let mut stats = DrawStats::default();
+
+        // (node id, primitive id) of everything in view, ordered by node
+        let mut visible: Vec<(usize, usize)> = Vec::new();
+        for (node_id, node) in self.nodes.iter().enumerate() {
+            let mesh = match node.mesh_id {
+                Some(mesh_id) => &self.meshes[mesh_id],
+                None => continue,
+            };
+            let node_visible = match node.bounds {
+                Some(bounds) => frustum.intersects(&bounds),
+                None => true,
+            };
+            for primitive_id in mesh.primitive_ids.clone() {
+                // No point testing a single primitive against the same bounds again
+                let bounds = &self.primitives[primitive_id].bounds;
+                let primitive_visible = node_visible
+                    && (node.bounds.is_none()
+                        || mesh.primitive_ids.len() == 1
+                        || frustum.intersects(&bounds.transform(&node.transform)));
+                if primitive_visible {
+                    visible.push((node_id, primitive_id));
+                    stats.drawn += 1;
+                } else {
+                    stats.culled += 1;
+                }
+            }
+        }
+
+        // Static primitives are drawn with a few indirect draws where supported,
+        // otherwise those visible on several nodes are drawn instanced.
+        // The selected node is left out to be drawn highlighted
+        let indirect_supported = shaders.phong_indirect.is_some();
+        let mut batches: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        let mut indirect: Vec<(usize, usize)> = Vec::new();
+        let mut single: Vec<(usize, usize)> = Vec::new();
+        for &(node_id, primitive_id) in visible.iter() {
+            let node = &self.nodes[node_id];
+            let primitive = &self.primitives[primitive_id];
+            let instanceable = primitive.instanceable()
+                && !(primitive.skinned && node.skin_id.is_some())
+                && self.selected != Some(node_id);
+            if instanceable && indirect_supported {
+                indirect.push((node_id, primitive_id));
+            } else if instanceable {
+                batches.entry(primitive_id).or_default().push(node_id);
+            } else {
+                single.push((node_id, primitive_id));
+            }
+        }
+        let mut instanced: Vec<(&Primitive, Vec<usize>)> = Vec::new();
+        for (primitive_id, node_ids) in batches {
+            if node_ids.len() > 1 {
+                instanced.push((&self.primitives[primitive_id], node_ids));
+            } else {
+                single.push((node_ids[0], primitive_id));
+            }
+        }
+        single.sort_unstable(); // keep primitives of a node together
+        stats.draw_calls = single.len() + instanced.len();
+
+        unsafe {
+            // Primitives without COLOR_0 should not come out black
+            gl::VertexAttrib4f(2, 1.0, 1.0, 1.0, 1.0);
+            // Points and lines often come without NORMAL
+            gl::VertexAttrib3f(1, 0.0, 0.0, 1.0);
+        }
+
+        for &shading in [Shading::Phong, Shading::Pbr].iter() {
+            for &skinned in [false, true].iter() {
+                let shader = shaders.get(shading, skinned);
+                shader.set_used();
+                self.draw_pass(shader, shading, skinned, &single)?;
+            }
+            let shader = shaders.instanced(shading);
+            shader.set_used();
+            self.draw_instanced_pass(shader, shading, stream, &instanced)?;
+        }
+        if !indirect.is_empty() {
+            stats.draw_calls += self.draw_indirect(shaders, stream, &mut indirect)?;
+        }
+
+        Ok(stats)
+    }
+
+    /// Draw the primitives whose material uses the given shading model
+    /// and which either are or aren't skinned
+    fn draw_pass(
+        &self,
+        shader: &Program,
+        shading: Shading,
+        skinned: bool,
+        visible: &[(usize, usize)],
+    ) -> Result<(), SceneError> {
+        set_texture_units(shader, shading)?;
+
+        let mut uniforms_node_id = None;
+        let mut bound_arena = None;
+        for &(node_id, primitive_id) in visible {
+            let node = &self.nodes[node_id];
+            let primitive = &self.primitives[primitive_id];
+            let material = self.primitive_material(primitive);
+            let primitive_skinned = primitive.skinned && node.skin_id.is_some();
+            if material.shading != shading || primitive_skinned != skinned {
+                continue;
+            }
+            if uniforms_node_id != Some(node_id) {
+                shader.set_mat4("model", &node.transform)?;
+                let highlight = if self.selected == Some(node_id) {
+                    HIGHLIGHT_COLOR
+                } else {
+                    Vec3::ZERO
+                };
+                shader.set_vec3("highlight", &highlight)?;
+                if skinned {
+                    shader.set_mat4_array("joint_matrices", &node.joint_matrices)?;
+                }
+                uniforms_node_id = Some(node_id);
+            }
+            material.bind(shader, &self.textures, &self.default_textures)?;
+            if let Some(morph_targets) = &primitive.morph_targets {
+                morph_targets.apply(&node.weights);
+            }
+            primitive.draw(&self.arenas, &mut bound_arena);
+        }
         Ok(())
     }
+
+    /// Draw each primitive once for all the nodes it's on
+    fn draw_instanced_pass(
+        &self,
+        shader: &Program,
+        shading: Shading,
+        stream: &mut RingBuffer,
+        batches: &[(&Primitive, Vec<usize>)],
+    ) -> Result<(), SceneError> {
+        set_texture_units(shader, shading)?;
+        shader.set_vec3("highlight", &Vec3::ZERO)?;
+
+        let instance_layout = Instance::layout().divisor(1);
+        let mut instances: Vec<Instance> = Vec::new();
+        let mut bound_arena = None;
+        for (primitive, node_ids) in batches {
+            let material = self.primitive_material(primitive);
+            if material.shading != shading {
+                continue;
+            }
+            instances.clear();
+            instances.extend(
+                node_ids
+                    .iter()
+                    .map(|&id| Instance::new(&self.nodes[id].transform)),
+            );
+            let slice = push_to_stream(stream, &instances, std::mem::align_of::<Instance>())?;
+            material.bind(shader, &self.textures, &self.default_textures)?;
+            primitive.draw_instanced(
+                &self.arenas,
+                &mut bound_arena,
+                stream,
+                slice,
+                &instance_layout,
+            );
+        }
+        Ok(())
+    }
+
+    /// Submit static primitives with one glMultiDrawElementsIndirect per material,
+    /// or glMultiDrawArraysIndirect for non-indexed ones. Returns the number of draw calls
+    fn draw_indirect(
+        &self,
+        shaders: &SceneShaders,
+        stream: &mut RingBuffer,
+        draws: &mut [(usize, usize)],
+    ) -> Result<usize, SceneError> {
+        // Draws in a call must share the material, vertex array, topology and whether they're indexed
+        let batch_key = |primitive: &Primitive| match &primitive.geometry {
+            Geometry::Shared {
+                arena_id, indices, ..
+            } => (
+                primitive.material_id,
+                *arena_id,
+                primitive.mode,
+                indices.is_some(),
+            ),
+            Geometry::Own { .. } => unreachable!("only arena primitives are drawn indirect"),
+        };
+        draws.sort_by_key(|&(_, primitive_id)| batch_key(&self.primitives[primitive_id]));
+
+        let mut element_commands: Vec<DrawElementsIndirectCommand> = Vec::new();
+        let mut array_commands: Vec<DrawArraysIndirectCommand> = Vec::new();
+        let mut transforms: Vec<Mat4> = Vec::with_capacity(draws.len());
+        // (first draw, number of draws, first command)
+        let mut batches: Vec<(usize, usize, usize)> = Vec::new();
+        for (i, &(node_id, primitive_id)) in draws.iter().enumerate() {
+            let primitive = &self.primitives[primitive_id];
+            let first_command = match &primitive.geometry {
+                Geometry::Shared {
+                    base_vertex,
+                    indices: Some(indices),
+                    ..
+                } => {
+                    element_commands.push(DrawElementsIndirectCommand {
+                        count: indices.len() as u32,
+                        instance_count: 1,
+                        first_index: indices.start as u32,
+                        base_vertex: *base_vertex as i32,
+                        base_instance: 0,
+                    });
+                    element_commands.len() - 1
+                }
+                Geometry::Shared {
+                    base_vertex,
+                    num_vertices,
+                    indices: None,
+                    ..
+                } => {
+                    array_commands.push(DrawArraysIndirectCommand {
+                        count: *num_vertices as u32,
+                        instance_count: 1,
+                        first: *base_vertex as u32,
+                        base_instance: 0,
+                    });
+                    array_commands.len() - 1
+                }
+                Geometry::Own { .. } => unreachable!("only arena primitives are drawn indirect"),
+            };
+            transforms.push(self.nodes[node_id].transform);
+
+            let new_batch = match batches.last() {
+                Some(&(first, _, _)) => {
+                    batch_key(&self.primitives[draws[first].1]) != batch_key(primitive)
+                }
+                None => true,
+            };
+            if new_batch {
+                batches.push((i, 0, first_command));
+            }
+            batches.last_mut().unwrap().1 += 1;
+        }
+
+        let element_commands = push_to_stream(
+            stream,
+            &element_commands,
+            std::mem::align_of::<DrawElementsIndirectCommand>(),
+        )?;
+        let array_commands = push_to_stream(
+            stream,
+            &array_commands,
+            std::mem::align_of::<DrawArraysIndirectCommand>(),
+        )?;
+        let storage_alignment = stream.storage_alignment();
+        let transforms = push_to_stream(stream, &transforms, storage_alignment)?;
+        stream.bind_as_indirect_buffer();
+        stream.bind_as_storage_buffer(0, transforms);
+
+        for &shading in [Shading::Phong, Shading::Pbr].iter() {
+            if let Some(shader) = shaders.indirect(shading) {
+                shader.set_used();
+                set_texture_units(shader, shading)?;
+                shader.set_vec3("highlight", &Vec3::ZERO)?;
+            }
+        }
+
+        for &(first, count, first_command) in batches.iter() {
+            let primitive = &self.primitives[draws[first].1];
+            let (_, arena_id, mode, indexed) = batch_key(primitive);
+            let material = self.primitive_material(primitive);
+            let shader = shaders.indirect(material.shading).unwrap();
+            shader.set_used();
+            shader.set_int("draw_offset", first as i32)?;
+            material.bind(shader, &self.textures, &self.default_textures)?;
+            self.arenas[arena_id].vao.bind();
+            unsafe {
+                if indexed {
+                    let offset = element_commands.offset
+                        + first_command * std::mem::size_of::<DrawElementsIndirectCommand>();
+                    gl::MultiDrawElementsIndirect(
+                        mode,
+                        gl::UNSIGNED_INT,
+                        offset as *const GLvoid,
+                        count as i32,
+                        0,
+                    );
+                } else {
+                    let offset = array_commands.offset
+                        + first_command * std::mem::size_of::<DrawArraysIndirectCommand>();
+                    gl::MultiDrawArraysIndirect(mode, offset as *const GLvoid, count as i32, 0);
+                }
+            }
+            gl_check_error!();
+        }
+
+        Ok(batches.len())
+    }
+
+    fn primitive_material(&self, primitive: &Primitive) -> &Material {
+        match primitive.material_id {
+            Some(id) => &self.materials[id],
+            None => &self.default_material,
+        }
+    }
 }
 
-/// Update the node and its children with the transform and store the final transforms
-fn recursive_update_transforms(gltf_node: gltf::Node, nodes: &mut [Node], transform: &Mat4) {
-    let node = &mut nodes[gltf_node.index()];
+fn push_to_stream<T: Pod>(
+    stream: &mut RingBuffer,
+    data: &[T],
+    alignment: usize,
+) -> Result<RingSlice, SceneError> {
+    let size = stream.frame_size();
+    stream
+        .push(data, alignment)
+        .ok_or(SceneError::StreamBufferFull { size })
+}
 
-    // Apply parent transform
-    let final_transform = *transform * node.transform;
-    node.transform = final_transform;
+/// Point material samplers to their texture units
+fn set_texture_units(shader: &Program, shading: Shading) -> Result<(), SceneError> {
+    shader.set_texture_unit("material.base_color_texture", BASE_COLOR_UNIT)?;
+    shader.set_texture_unit("material.normal_texture", NORMAL_UNIT)?;
+    shader.set_texture_unit("material.occlusion_texture", OCCLUSION_UNIT)?;
+    shader.set_texture_unit("material.emissive_texture", EMISSIVE_UNIT)?;
+    if shading == Shading::Pbr {
+        shader.set_texture_unit(
+            "material.metallic_roughness_texture",
+            METALLIC_ROUGHNESS_UNIT,
+        )?;
+    }
+    Ok(())
+}
 
-    for child in gltf_node.children() {
-        recursive_update_transforms(child, nodes, &final_transform);
+/// Store final transforms in the node and its children if either they or their parent are dirty.
+/// Returns whether anything in the subtree was updated
+fn recursive_update_transforms(
+    node_id: usize,
+    nodes: &mut [Node],
+    parent_transform: &Mat4,
+    parent_dirty: bool,
+) -> bool {
+    let node = &mut nodes[node_id];
+    let dirty = node.dirty || parent_dirty;
+    if dirty {
+        node.transform = *parent_transform * node.pose.matrix();
+        node.bounds = node.local_bounds.map(|b| b.transform(&node.transform));
+        node.dirty = false;
     }
+
+    let transform = node.transform;
+    let mut changed = dirty;
+    for i in 0..nodes[node_id].children_ids.len() {
+        let child_id = nodes[node_id].children_ids[i];
+        changed |= recursive_update_transforms(child_id, nodes, &transform, dirty);
+    }
+    changed
 }
 
 // ==================================== Node ======================================================
 
 #[derive(Debug)]
 struct Node {
+    name: Option<String>,
     mesh_id: Option<usize>,
+    skin_id: Option<usize>,
+    parent_id: Option<usize>,
     children_ids: Vec<usize>,
 
+    /// Transform relative to the parent
+    local: Transform,
+    /// Local transform after animation
+    pose: Transform,
+    /// The pose has changed since the final transform was computed
+    dirty: bool,
+
     /// The final transform matrix (including parent transforms)
     transform: Mat4,
+
+    /// Bounds of all primitives in the mesh. None if the node can't be culled
+    local_bounds: Option<Aabb>,
+    /// World space bounds as of the last transform update
+    bounds: Option<Aabb>,
+
+    /// Per-joint transforms for skinned nodes, relative to the node itself
+    joint_matrices: Vec<Mat4>,
+
+    /// Morph target weights from the file
+    default_weights: Vec<f32>,
+    /// Morph target weights after animation
+    weights: Vec<f32>,
 }
 
 impl Node {
     fn from_gltf(node: gltf::Node) -> Self {
+        // Node weights override the mesh ones, both default to zero
+        let default_weights = match (node.weights(), node.mesh()) {
+            (Some(weights), _) => weights.to_vec(),
+            (None, Some(mesh)) => match mesh.weights() {
+                Some(weights) => weights.to_vec(),
+                None => {
+                    let num_targets = mesh
+                        .primitives()
+                        .map(|p| p.morph_targets().len())
+                        .max()
+                        .unwrap_or(0);
+                    vec![0.0; num_targets]
+                }
+            },
+            (None, None) => Vec::new(),
+        };
+
+        let local = Transform::from_gltf(node.transform());
+
         Node {
+            name: node.name().map(|name| name.to_owned()),
             mesh_id: node.mesh().map(|m| m.index()),
+            skin_id: node.skin().map(|s| s.index()),
+            parent_id: None, // set once all nodes are created
             children_ids: node.children().map(|n| n.index()).collect(),
-            transform: Mat4::from_cols_array_2d(&node.transform().matrix()),
+            local,
+            pose: local,
+            dirty: true,
+            transform: Mat4::IDENTITY, // set once the hierarchy is known
+            local_bounds: None,        // set once meshes are loaded
+            bounds: None,
+            joint_matrices: Vec::new(),
+            weights: default_weights.clone(),
+            default_weights,
+        }
+    }
+}
+
+// ==================================== Skin ======================================================
+
+#[derive(Debug)]
+struct Skin {
+    joint_ids: Vec<usize>,
+    inverse_bind_matrices: Vec<Mat4>,
+}
+
+impl Skin {
+    fn from_gltf(skin: gltf::Skin, buffers: &[gltf::buffer::Data]) -> Self {
+        let joint_ids: Vec<usize> = skin.joints().map(|joint| joint.index()).collect();
+
+        // Missing inverse bind matrices mean they're all identity
+        let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+        let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+            Some(matrices) => matrices.map(|m| Mat4::from_cols_array_2d(&m)).collect(),
+            None => vec![Mat4::IDENTITY; joint_ids.len()],
+        };
+
+        Skin {
+            joint_ids,
+            inverse_bind_matrices,
         }
     }
 }
@@ -107,102 +1090,1100 @@ impl Node {
 
 #[derive(Debug)]
 struct Mesh {
-    primitives: Vec<Primitive>,
+    /// Range in `Scene::primitives`
+    primitive_ids: Range<usize>,
 }
 
 impl Mesh {
-    fn from_gltf(mesh: gltf::Mesh, buffers: &[Buffer]) -> Self {
-        let primitives = mesh
-            .primitives()
-            .map(|primitive| Primitive::from_gltf(primitive, buffers))
-            .collect();
-        Mesh { primitives }
+    /// Primitives are appended to `primitives`.
+    /// Those which can't be drawn are skipped and their errors added to `warnings`
+    fn from_gltf(
+        mesh: gltf::Mesh,
+        buffer_data: &[gltf::buffer::Data],
+        primitives: &mut Vec<Primitive>,
+        arenas: &mut ArenaBuilder,
+        warnings: &mut Vec<SceneError>,
+    ) -> Self {
+        let first_id = primitives.len();
+        for primitive in mesh.primitives() {
+            let errors = primitive_errors(&mesh, &primitive);
+            if errors.is_empty() {
+                primitives.push(Primitive::from_gltf(
+                    &mesh,
+                    primitive,
+                    buffer_data,
+                    arenas,
+                    warnings,
+                ));
+            } else {
+                warnings.extend(errors);
+            }
+        }
+        Mesh {
+            primitive_ids: first_id..primitives.len(),
+        }
+    }
+}
+
+// ==================================== Material ==================================================
+
+/// glTF metallic-roughness material. Textures are indices into `Scene::textures`
+#[derive(Debug, Uniforms)]
+struct Material {
+    #[uniform(skip)]
+    name: Option<String>,
+    #[uniform(skip)]
+    shading: Shading,
+
+    base_color_factor: Vec4,
+    #[uniform(skip)]
+    base_color_texture: Option<usize>,
+
+    #[uniform(optional)] // PBR only
+    metallic_factor: f32,
+    #[uniform(optional)]
+    roughness_factor: f32,
+    #[uniform(skip)]
+    metallic_roughness_texture: Option<usize>,
+
+    #[uniform(skip)]
+    normal_texture: Option<usize>,
+    #[uniform(skip)] // depends on the texture, set in `bind`
+    normal_scale: f32,
+
+    #[uniform(skip)]
+    occlusion_texture: Option<usize>,
+    occlusion_strength: f32,
+
+    emissive_factor: Vec3,
+    #[uniform(skip)]
+    emissive_texture: Option<usize>,
+}
+
+impl Material {
+    fn from_gltf(material: gltf::Material) -> Self {
+        let pbr = material.pbr_metallic_roughness();
+        let normal = material.normal_texture();
+        let occlusion = material.occlusion_texture();
+
+        Material {
+            name: material.name().map(|name| name.to_owned()),
+            shading: Shading::Pbr,
+
+            base_color_factor: Vec4::from(pbr.base_color_factor()),
+            base_color_texture: pbr.base_color_texture().map(|t| t.texture().index()),
+
+            metallic_factor: pbr.metallic_factor(),
+            roughness_factor: pbr.roughness_factor(),
+            metallic_roughness_texture: pbr
+                .metallic_roughness_texture()
+                .map(|t| t.texture().index()),
+
+            normal_scale: normal.as_ref().map_or(1.0, |t| t.scale()),
+            normal_texture: normal.map(|t| t.texture().index()),
+
+            occlusion_strength: occlusion.as_ref().map_or(1.0, |t| t.strength()),
+            occlusion_texture: occlusion.map(|t| t.texture().index()),
+
+            emissive_factor: Vec3::from(material.emissive_factor()),
+            emissive_texture: material.emissive_texture().map(|t| t.texture().index()),
+        }
+    }
+
+    /// Set material uniforms and bind its textures to the material texture units
+    fn bind(
+        &self,
+        shader: &Program,
+        textures: &[Texture],
+        defaults: &DefaultTextures,
+    ) -> Result<(), SceneError> {
+        let texture_or = |id: Option<usize>, default| id.map_or(default, |id| &textures[id]);
+
+        texture_or(self.base_color_texture, &defaults.white).bind(BASE_COLOR_UNIT);
+        texture_or(self.metallic_roughness_texture, &defaults.white).bind(METALLIC_ROUGHNESS_UNIT);
+        texture_or(self.normal_texture, &defaults.flat_normal).bind(NORMAL_UNIT);
+        texture_or(self.occlusion_texture, &defaults.white).bind(OCCLUSION_UNIT);
+        texture_or(self.emissive_texture, &defaults.white).bind(EMISSIVE_UNIT);
+
+        shader.set_uniforms("material", self)?;
+
+        // A zero scale leaves the surface normal untouched
+        let normal_scale = if self.normal_texture.is_some() {
+            self.normal_scale
+        } else {
+            0.0
+        };
+        shader.set_float("material.normal_scale", normal_scale)?;
+
+        Ok(())
+    }
+}
+
+impl Default for Material {
+    /// The glTF default material
+    fn default() -> Self {
+        Material {
+            name: None,
+            shading: Shading::Pbr,
+            base_color_factor: Vec4::ONE,
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: Vec3::ZERO,
+            emissive_texture: None,
+        }
+    }
+}
+
+/// 1x1 textures which make a missing material texture a no-op in the shader
+#[derive(Debug)]
+struct DefaultTextures {
+    white: Texture,
+    flat_normal: Texture,
+}
+
+impl DefaultTextures {
+    fn new() -> Self {
+        DefaultTextures {
+            white: Texture::new()
+                .set_default_parameters()
+                .set_color_2d([255, 255, 255, 255]),
+            flat_normal: Texture::new()
+                .set_default_parameters()
+                .set_color_2d([128, 128, 255, 255]),
+        }
     }
 }
 
 // ==================================== Primitive =================================================
 
+/// Where the vertices and indices of a primitive are stored
+#[derive(Debug)]
+enum Geometry {
+    /// A range of vertices in one of the scene's vertex arenas
+    Shared {
+        arena_id: usize,
+        /// Position of the primitive's first vertex in the arena
+        base_vertex: usize,
+        num_vertices: usize,
+        /// Range of the shared index buffer, None for non-indexed primitives
+        indices: Option<Range<usize>>,
+    },
+    /// Morphed primitives rewrite their positions and normals every frame,
+    /// so they keep a vertex array of their own
+    Own {
+        vao: VertexArray,
+        /// None for non-indexed primitives
+        ebo: Option<ElementBuffer>,
+        num_vertices: usize,
+        /// Indices and the attributes which aren't morphed
+        _buffers: Vec<Buffer>,
+    },
+}
+
 #[derive(Debug)]
 struct Primitive {
-    vao: VertexArray,
-    ebo: ElementBuffer,
+    geometry: Geometry,
+    /// Topology: gl::TRIANGLES, gl::LINES etc.
+    mode: GLenum,
+    material_id: Option<usize>,
+
+    /// Has joints and weights
+    skinned: bool,
+
+    morph_targets: Option<MorphTargets>,
+
+    /// Local space bounds covering all morph targets, but not skinning
+    bounds: Aabb,
+    /// Triangles in the rest pose for ray casts, empty for points and lines
+    bvh: Bvh,
 }
 
 impl Primitive {
-    fn from_gltf(primitive: gltf::Primitive, buffers: &[Buffer]) -> Self {
-        let indices = primitive.indices().unwrap();
-        let ebo = ElementBuffer {
-            num_elements: indices.count(),
-            element_type: indices.data_type().as_gl_enum(),
-            buffer_offset: indices.offset() + indices.view().unwrap().offset(),
+    /// Expects a primitive without `primitive_errors`. Morph targets which still can't be read
+    /// are added to `warnings` and the primitive is drawn without them
+    fn from_gltf(
+        mesh: &gltf::Mesh,
+        primitive: gltf::Primitive,
+        buffer_data: &[gltf::buffer::Data],
+        arenas: &mut ArenaBuilder,
+        warnings: &mut Vec<SceneError>,
+    ) -> Self {
+        let morph_targets = match MorphTargets::from_gltf(mesh, &primitive, buffer_data) {
+            Ok(morph_targets) => morph_targets,
+            Err(error) => {
+                warnings.push(error);
+                None
+            }
+        };
+        let geometry = match &morph_targets {
+            Some(morph_targets) => Primitive::own_geometry(&primitive, buffer_data, morph_targets),
+            None => arenas.add(&primitive, buffer_data),
         };
 
-        // Create buffers and describe attributes
+        let skinned = primitive.get(&Joints(0)).is_some() && primitive.get(&Weights(0)).is_some();
+
+        Primitive {
+            geometry,
+            mode: primitive.mode().as_gl_enum(),
+            material_id: primitive.material().index(),
+            skinned,
+            morph_targets,
+            bounds: primitive_bounds(&primitive, buffer_data),
+            bvh: Bvh::new(primitive_triangles(&primitive, buffer_data)),
+        }
+    }
+
+    /// Create a vertex array for a morphed primitive
+    fn own_geometry(
+        primitive: &gltf::Primitive,
+        buffer_data: &[gltf::buffer::Data],
+        morph_targets: &MorphTargets,
+    ) -> Geometry {
         let vao = VertexArray::new();
-        vao.bind();
-        for (attr, accessor) in primitive.attributes() {
-            let location: u32 = match attr {
-                Positions => 0,
-                Normals => 1,
-                Colors(_) => 2,
-                _ => continue, // skip the rest of attributes
+        let mut buffers = Vec::new();
+
+        let ebo = primitive.indices().map(|indices| {
+            let data = materialize_accessor(&indices, buffer_data);
+            let buffer = Buffer::new(&data, BufferUsage::Static);
+            vao.set_element_buffer(&buffer);
+            buffers.push(buffer);
+            ElementBuffer {
+                num_elements: indices.count(),
+                element_type: indices.data_type().as_gl_enum(),
+                buffer_offset: 0,
+            }
+        });
+
+        // Morphed positions and normals come from a separate buffer
+        morph_targets.set_attributes(&vao);
+        for (format, accessor) in primitive_attributes(primitive) {
+            if format.location == 0 || format.location == 1 {
+                continue;
+            }
+            let data = materialize_accessor(&accessor, buffer_data);
+            let buffer = Buffer::new(&data, BufferUsage::Static);
+            vao.set_layout(
+                &buffer,
+                &VertexLayout::new(0).attribute(format.vertex_attribute(0)),
+            );
+            buffers.push(buffer);
+        }
+        vao.unbind(); // done
+
+        Geometry::Own {
+            vao,
+            ebo,
+            num_vertices: primitive.get(&Positions).map_or(0, |p| p.count()),
+            _buffers: buffers,
+        }
+    }
+
+    /// Only primitives in an arena can be drawn instanced
+    fn instanceable(&self) -> bool {
+        matches!(self.geometry, Geometry::Shared { .. })
+    }
+
+    /// Draw the primitive. `bound_arena` is the arena whose vertex array is bound,
+    /// so that it's not bound again for each primitive
+    fn draw(&self, arenas: &[VertexArena], bound_arena: &mut Option<usize>) {
+        match &self.geometry {
+            Geometry::Shared {
+                arena_id,
+                base_vertex,
+                num_vertices,
+                indices,
+            } => {
+                if *bound_arena != Some(*arena_id) {
+                    arenas[*arena_id].vao.bind();
+                    *bound_arena = Some(*arena_id);
+                }
+                unsafe {
+                    match indices {
+                        Some(indices) => gl::DrawElementsBaseVertex(
+                            self.mode,
+                            indices.len() as i32,
+                            gl::UNSIGNED_INT,
+                            (indices.start * std::mem::size_of::<u32>()) as *const GLvoid,
+                            *base_vertex as i32,
+                        ),
+                        None => {
+                            gl::DrawArrays(self.mode, *base_vertex as i32, *num_vertices as i32)
+                        }
+                    }
+                }
+            }
+            Geometry::Own {
+                vao,
+                ebo,
+                num_vertices,
+                ..
+            } => {
+                vao.bind();
+                *bound_arena = None;
+                unsafe {
+                    match ebo {
+                        Some(ebo) => gl::DrawElements(
+                            self.mode,
+                            ebo.num_elements as i32,
+                            ebo.element_type,
+                            ebo.buffer_offset as *const GLvoid,
+                        ),
+                        None => gl::DrawArrays(self.mode, 0, *num_vertices as i32),
+                    }
+                }
+            }
+        }
+        gl_check_error!();
+    }
+
+    /// Draw a copy of the primitive for each instance in the stream buffer slice
+    fn draw_instanced(
+        &self,
+        arenas: &[VertexArena],
+        bound_arena: &mut Option<usize>,
+        stream: &RingBuffer,
+        instances: RingSlice,
+        instance_layout: &VertexLayout,
+    ) {
+        let (arena_id, base_vertex, num_vertices, indices) = match &self.geometry {
+            Geometry::Shared {
+                arena_id,
+                base_vertex,
+                num_vertices,
+                indices,
+            } => (*arena_id, *base_vertex, *num_vertices, indices),
+            Geometry::Own { .. } => return,
+        };
+        if *bound_arena != Some(arena_id) {
+            arenas[arena_id].vao.bind();
+            *bound_arena = Some(arena_id);
+        }
+        let arena = &arenas[arena_id];
+        if !arena.instance_format_set.replace(true) {
+            arena.vao.set_ring_format(instance_layout);
+        }
+        arena
+            .vao
+            .bind_ring_slice(stream, instances, instance_layout);
+        let num_instances = (instances.size / std::mem::size_of::<Instance>()) as i32;
+        unsafe {
+            match indices {
+                Some(indices) => gl::DrawElementsInstancedBaseVertex(
+                    self.mode,
+                    indices.len() as i32,
+                    gl::UNSIGNED_INT,
+                    (indices.start * std::mem::size_of::<u32>()) as *const GLvoid,
+                    num_instances,
+                    base_vertex as i32,
+                ),
+                None => gl::DrawArraysInstanced(
+                    self.mode,
+                    base_vertex as i32,
+                    num_vertices as i32,
+                    num_instances,
+                ),
+            }
+        }
+        gl_check_error!();
+    }
+}
+
+/// How a vertex attribute is stored. Primitives with the same formats share an arena
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct AttributeFormat {
+    location: u32,
+    dimensions: Dimensions,
+    data_type: DataType,
+    normalized: bool,
+}
+
+impl AttributeFormat {
+    /// The attribute in a tightly packed block starting at `offset`
+    fn vertex_attribute(&self, offset: usize) -> VertexAttribute {
+        VertexAttribute {
+            name: match self.location {
+                0 => "POSITION",
+                1 => "NORMAL",
+                2 => "COLOR_0",
+                3 => "TEXCOORD_0",
+                4 => "JOINTS_0",
+                _ => "WEIGHTS_0",
+            },
+            location: self.location,
+            components: self.dimensions.multiplicity() as i32,
+            data_type: self.data_type.as_gl_enum(),
+            normalized: self.normalized,
+            integer: self.location == 4, // joint indices
+            offset,
+        }
+    }
+}
+
+/// Attributes the renderer uses, ordered by location
+fn primitive_attributes<'a>(
+    primitive: &gltf::Primitive<'a>,
+) -> Vec<(AttributeFormat, gltf::Accessor<'a>)> {
+    let mut attributes: Vec<(AttributeFormat, gltf::Accessor)> = primitive
+        .attributes()
+        .filter_map(|(semantic, accessor)| {
+            let format = AttributeFormat {
+                location: attribute_location(&semantic)?,
+                dimensions: accessor.dimensions(),
+                data_type: accessor.data_type(),
+                normalized: accessor.normalized(),
             };
-            let buffer_view = accessor.view().unwrap();
+            Some((format, accessor))
+        })
+        .collect();
+    attributes.sort_by_key(|(format, _)| format.location);
+    attributes
+}
 
-            let num_components = accessor.dimensions().multiplicity();
-            let data_type = accessor.data_type();
-            let stride = buffer_view.stride().unwrap_or(0);
-            let offset = buffer_view.offset() + accessor.offset();
+/// Shader location of a vertex attribute, None if the renderer doesn't use it
+fn attribute_location(semantic: &gltf::Semantic) -> Option<u32> {
+    match semantic {
+        Positions => Some(0),
+        Normals => Some(1),
+        Colors(0) => Some(2),
+        TexCoords(0) => Some(3),
+        Joints(0) => Some(4),
+        Weights(0) => Some(5),
+        _ => None,
+    }
+}
 
-            let buffer = &buffers[buffer_view.buffer().index()];
-            buffer.bind_as_ebo();
-            unsafe {
-                gl::VertexAttribPointer(
-                    location,
-                    num_components as i32,
-                    data_type.as_gl_enum(),
-                    gl::FALSE,
-                    stride as i32,
-                    offset as *const GLvoid,
-                );
-                gl::EnableVertexAttribArray(location);
+// ==================================== VertexArena ===============================================
+
+/// One vertex array shared by all primitives with the same attribute formats.
+/// Each attribute is a tightly packed block in a single buffer
+#[derive(Debug)]
+struct VertexArena {
+    vao: VertexArray,
+    _vbo: Buffer,
+    /// Indices of all arenas, referenced by each vertex array
+    _index_buffer: Rc<Buffer<u32>>,
+    /// The per-instance attributes are described on the first instanced draw,
+    /// later draws only point them at their slice of the stream buffer
+    instance_format_set: Cell<bool>,
+}
+
+/// Collects the vertices and indices of a scene before they're uploaded
+#[derive(Default)]
+struct ArenaBuilder {
+    layouts: Vec<ArenaLayout>,
+    indices: Vec<u32>,
+}
+
+/// Vertex data of a future arena
+struct ArenaLayout {
+    formats: Vec<AttributeFormat>,
+    /// Values of each attribute, in the order of `formats`
+    blocks: Vec<Vec<u8>>,
+    num_vertices: usize,
+}
+
+impl ArenaBuilder {
+    /// Append the vertices and indices of a primitive
+    fn add(&mut self, primitive: &gltf::Primitive, buffer_data: &[gltf::buffer::Data]) -> Geometry {
+        let attributes = primitive_attributes(primitive);
+        let formats: Vec<AttributeFormat> = attributes.iter().map(|(format, _)| *format).collect();
+        let arena_id = match self.layouts.iter().position(|l| l.formats == formats) {
+            Some(arena_id) => arena_id,
+            None => {
+                self.layouts.push(ArenaLayout {
+                    blocks: vec![Vec::new(); formats.len()],
+                    formats,
+                    num_vertices: 0,
+                });
+                self.layouts.len() - 1
             }
+        };
+
+        let layout = &mut self.layouts[arena_id];
+        for (block, (_, accessor)) in layout.blocks.iter_mut().zip(attributes.iter()) {
+            block.extend(materialize_accessor(accessor, buffer_data));
+        }
+        let num_vertices = primitive.get(&Positions).map_or(0, |p| p.count());
+        let base_vertex = layout.num_vertices;
+        layout.num_vertices += num_vertices;
+
+        let reader = primitive.reader(|buffer| Some(&buffer_data[buffer.index()]));
+        let indices = reader.read_indices().map(|indices| {
+            let first_index = self.indices.len();
+            self.indices.extend(indices.into_u32());
+            first_index..self.indices.len()
+        });
+
+        Geometry::Shared {
+            arena_id,
+            base_vertex,
+            num_vertices,
+            indices,
+        }
+    }
+
+    /// Upload the arenas along with the index buffer they share
+    fn build(self) -> Vec<VertexArena> {
+        let index_buffer = Rc::new(Buffer::new(&self.indices, BufferUsage::Static));
+
+        self.layouts
+            .into_iter()
+            .map(|layout| {
+                // Keep every block 4-byte aligned
+                let mut data: Vec<u8> = Vec::new();
+                let mut offsets = Vec::new();
+                for block in layout.blocks.iter() {
+                    offsets.push(data.len());
+                    data.extend(block);
+                    data.resize(data.len().next_multiple_of(4), 0);
+                }
+
+                let vertex_layout = layout.formats.iter().zip(offsets.iter()).fold(
+                    VertexLayout::new(0),
+                    |vertex_layout, (format, &offset)| {
+                        vertex_layout.attribute(format.vertex_attribute(offset))
+                    },
+                );
+                let vao = VertexArray::new();
+                let vbo = Buffer::new(&data, BufferUsage::Static);
+                vao.set_layout(&vbo, &vertex_layout);
+                vao.set_element_buffer(&index_buffer);
+                vao.unbind();
+
+                VertexArena {
+                    vao,
+                    _vbo: vbo,
+                    _index_buffer: Rc::clone(&index_buffer),
+                    instance_format_set: Cell::new(false),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Per-instance attributes of instanced draws, the model matrix takes a location per column
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable, Vertex)]
+struct Instance {
+    #[vertex(location = 6)]
+    model_x: Vec4,
+    #[vertex(location = 7)]
+    model_y: Vec4,
+    #[vertex(location = 8)]
+    model_z: Vec4,
+    #[vertex(location = 9)]
+    model_w: Vec4,
+}
 
-            if location == 0 {
-                assert_eq!(data_type, DataType::F32);
-                assert_eq!(num_components, 3);
+impl Instance {
+    fn new(model: &Mat4) -> Self {
+        Instance {
+            model_x: model.x_axis,
+            model_y: model.y_axis,
+            model_z: model.z_axis,
+            model_w: model.w_axis,
+        }
+    }
+}
+
+// ==================================== Indirect draws ============================================
+
+/// Layout expected by glMultiDrawElementsIndirect
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct DrawElementsIndirectCommand {
+    count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    base_instance: u32,
+}
+
+/// Layout expected by glMultiDrawArraysIndirect
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct DrawArraysIndirectCommand {
+    count: u32,
+    instance_count: u32,
+    first: u32,
+    base_instance: u32,
+}
+
+// ==================================== Validation ================================================
+
+/// File-wide problems: extensions, scenes, skins and materials
+fn document_errors(document: &gltf::Document) -> Vec<SceneError> {
+    let mut errors = Vec::new();
+
+    for extension in document.extensions_required() {
+        if !SUPPORTED_EXTENSIONS.contains(&extension) {
+            errors.push(SceneError::UnsupportedExtension(extension.to_owned()));
+        }
+    }
+
+    if document.scenes().len() == 0 {
+        errors.push(SceneError::NoScene);
+    }
+
+    for skin in document.skins() {
+        let count = skin.joints().count();
+        if count > MAX_JOINTS {
+            errors.push(SceneError::TooManyJoints {
+                skin: skin.index(),
+                count,
+            });
+        }
+    }
+
+    for material in document.materials() {
+        let pbr = material.pbr_metallic_roughness();
+        let sets = [
+            pbr.base_color_texture().map(|t| t.tex_coord()),
+            pbr.metallic_roughness_texture().map(|t| t.tex_coord()),
+            material.normal_texture().map(|t| t.tex_coord()),
+            material.occlusion_texture().map(|t| t.tex_coord()),
+            material.emissive_texture().map(|t| t.tex_coord()),
+        ];
+        if let Some(set) = sets.iter().flatten().find(|&&set| set != 0) {
+            errors.push(SceneError::UnsupportedTexCoord {
+                material: material.index().unwrap_or(0),
+                set: *set,
+            });
+        }
+    }
+
+    errors
+}
+
+/// Problems that prevent a primitive from being drawn
+fn primitive_errors(mesh: &gltf::Mesh, primitive: &gltf::Primitive) -> Vec<SceneError> {
+    let mut errors = Vec::new();
+
+    if primitive.get(&Positions).is_none() {
+        errors.push(SceneError::MissingPositions {
+            mesh: mesh.index(),
+            primitive: primitive.index(),
+        });
+    }
+
+    for (semantic, accessor) in primitive.attributes() {
+        if attribute_location(&semantic).is_none() {
+            continue;
+        }
+        if let Err(error) = check_attribute_format(&semantic, &accessor) {
+            errors.push(error);
+        }
+    }
+
+    // Deltas are read as floats like quantized positions, one per vertex
+    let vertex_count = primitive.get(&Positions).map(|accessor| accessor.count());
+    for (target_id, target) in primitive.morph_targets().enumerate() {
+        let deltas = [(Positions, target.positions()), (Normals, target.normals())];
+        for (semantic, accessor) in deltas {
+            let readable = accessor.is_none_or(|accessor| {
+                accessor.dimensions() == Dimensions::Vec3
+                    && accessor.data_type() != DataType::U32
+                    && Some(accessor.count()) == vertex_count
+            });
+            if !readable {
+                errors.push(SceneError::InvalidMorphTarget {
+                    mesh: mesh.index(),
+                    primitive: primitive.index(),
+                    target: target_id,
+                    semantic: format!("{:?}", semantic),
+                });
             }
-            if location == 1 {
-                assert_eq!(data_type, DataType::F32);
-                assert_eq!(num_components, 3);
+        }
+    }
+
+    errors
+}
+
+/// Check the accessor against the formats allowed by the glTF spec and KHR_mesh_quantization
+fn check_attribute_format(
+    semantic: &gltf::Semantic,
+    accessor: &gltf::Accessor,
+) -> Result<(), SceneError> {
+    use DataType::*;
+
+    let data_type = accessor.data_type();
+    let dimensions = accessor.dimensions();
+    let normalized = accessor.normalized();
+
+    let supported = match semantic {
+        Positions => {
+            dimensions == Dimensions::Vec3
+                && match data_type {
+                    F32 => !normalized,
+                    I8 | U8 | I16 | U16 => true,
+                    U32 => false,
+                }
+        }
+        Normals => {
+            dimensions == Dimensions::Vec3
+                && match data_type {
+                    F32 => !normalized,
+                    I8 | I16 => normalized,
+                    _ => false,
+                }
+        }
+        TexCoords(_) => {
+            dimensions == Dimensions::Vec2
+                && match data_type {
+                    F32 => !normalized,
+                    I8 | U8 | I16 | U16 => true,
+                    U32 => false,
+                }
+        }
+        Colors(_) => {
+            (dimensions == Dimensions::Vec3 || dimensions == Dimensions::Vec4)
+                && match data_type {
+                    F32 => !normalized,
+                    U8 | U16 => normalized,
+                    _ => false,
+                }
+        }
+        Joints(_) => dimensions == Dimensions::Vec4 && (data_type == U8 || data_type == U16),
+        Weights(_) => {
+            dimensions == Dimensions::Vec4
+                && match data_type {
+                    F32 => !normalized,
+                    U8 | U16 => normalized,
+                    _ => false,
+                }
+        }
+        _ => true,
+    };
+
+    if supported {
+        Ok(())
+    } else {
+        Err(SceneError::UnsupportedAttribute {
+            semantic: format!("{:?}", semantic),
+            dimensions,
+            data_type,
+            normalized,
+        })
+    }
+}
+
+// ==================================== Accessors =================================================
+
+/// Accessor component type which can be converted to f32
+trait Component: gltf::accessor::Item + Copy {
+    fn to_f32(self, normalized: bool) -> f32;
+}
+
+impl Component for f32 {
+    fn to_f32(self, _normalized: bool) -> f32 {
+        self
+    }
+}
+
+// Normalized conversions follow the glTF spec (e.g. max(c / 127.0, -1.0) for signed bytes)
+macro_rules! impl_component {
+    ($t:ty, $signed:expr) => {
+        impl Component for $t {
+            fn to_f32(self, normalized: bool) -> f32 {
+                if !normalized {
+                    self as f32
+                } else if $signed {
+                    (self as f32 / <$t>::MAX as f32).max(-1.0)
+                } else {
+                    self as f32 / <$t>::MAX as f32
+                }
             }
-            if location == 2 {
-                assert_eq!(data_type, DataType::F32);
-                assert_eq!(num_components, 4);
+        }
+    };
+}
+
+impl_component!(i8, true);
+impl_component!(u8, false);
+impl_component!(i16, true);
+impl_component!(u16, false);
+
+/// Copy accessor elements into a tightly packed byte array, applying sparse substitutions.
+/// Accessors without a buffer view start out zeroed as the spec requires
+fn materialize_accessor(accessor: &gltf::Accessor, buffers: &[gltf::buffer::Data]) -> Vec<u8> {
+    let element_size = accessor.size();
+    let count = accessor.count();
+    let mut data = vec![0u8; element_size * count];
+
+    if let Some(view) = accessor.view() {
+        let buffer = &buffers[view.buffer().index()];
+        let stride = view.stride().unwrap_or(element_size);
+        let start = view.offset() + accessor.offset();
+        for (i, element) in data.chunks_exact_mut(element_size).enumerate() {
+            let offset = start + i * stride;
+            element.copy_from_slice(&buffer[offset..offset + element_size]);
+        }
+    }
+
+    if let Some(sparse) = accessor.sparse() {
+        let indices = sparse.indices();
+        let index_size = indices.index_type().size();
+        let index_view = indices.view();
+        let index_start = index_view.offset() + indices.offset() as usize;
+        let index_buffer = &buffers[index_view.buffer().index()];
+
+        let values = sparse.values();
+        let value_view = values.view();
+        let value_start = value_view.offset() + values.offset() as usize;
+        let value_buffer = &buffers[value_view.buffer().index()];
+
+        for i in 0..sparse.count() as usize {
+            let bytes = &index_buffer[index_start + i * index_size..][..index_size];
+            let index = match index_size {
+                1 => bytes[0] as usize,
+                2 => u16::from_le_bytes([bytes[0], bytes[1]]) as usize,
+                _ => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize,
+            };
+            if index >= count {
+                continue; // out of range, validation would have rejected the file
             }
+            let value = &value_buffer[value_start + i * element_size..][..element_size];
+            data[index * element_size..][..element_size].copy_from_slice(value);
+        }
+    }
+
+    data
+}
+
+/// Positions of each triangle for triangle, strip and fan primitives
+fn primitive_triangles(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+) -> Vec<[Vec3; 3]> {
+    use gltf::mesh::Mode;
+
+    let positions = match primitive
+        .get(&Positions)
+        .and_then(|p| read_vec3(&p, buffers))
+    {
+        Some(positions) => positions,
+        None => return Vec::new(),
+    };
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let indices: Vec<usize> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+        None => (0..positions.len()).collect(),
+    };
+    if indices.iter().any(|&i| i >= positions.len()) {
+        return Vec::new();
+    }
+
+    let triangle = |a: usize, b: usize, c: usize| [positions[a], positions[b], positions[c]];
+    match primitive.mode() {
+        Mode::Triangles => indices
+            .chunks_exact(3)
+            .map(|t| triangle(t[0], t[1], t[2]))
+            .collect(),
+        Mode::TriangleStrip => indices
+            .windows(3)
+            .map(|t| triangle(t[0], t[1], t[2]))
+            .collect(),
+        Mode::TriangleFan if !indices.is_empty() => indices[1..]
+            .windows(2)
+            .map(|t| triangle(indices[0], t[0], t[1]))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
 
-            // println!("== vertex attrib ==");
-            // println!("   location: {:?}", location);
-            // println!("   num_components {:?}", num_components);
-            // println!("   data_type: {:?}", data_type);
-            // println!("   stride: {:?}", stride);
-            // println!("   offset: {:?}", offset);
+/// Bounds of POSITION, expanded by the largest displacement of each morph target
+fn primitive_bounds(primitive: &gltf::Primitive, buffers: &[gltf::buffer::Data]) -> Aabb {
+    let mut bounds = match primitive.get(&Positions) {
+        Some(positions) => accessor_bounds(&positions, buffers),
+        None => return Aabb::EMPTY,
+    };
+    for target in primitive.morph_targets() {
+        if let Some(deltas) = target.positions() {
+            let deltas = accessor_bounds(&deltas, buffers);
+            bounds.min += deltas.min.min(Vec3::ZERO);
+            bounds.max += deltas.max.max(Vec3::ZERO);
         }
-        vao.unbind(); // done
+    }
+    bounds
+}
 
-        Primitive { vao, ebo }
+/// Bounds of a vec3 accessor from its min and max.
+/// These hold raw values for normalized accessors, so those are read in full
+fn accessor_bounds(accessor: &gltf::Accessor, buffers: &[gltf::buffer::Data]) -> Aabb {
+    fn parse(value: Option<gltf::json::Value>) -> Option<Vec3> {
+        let value = value?;
+        let array = value.as_array()?;
+        match array.as_slice() {
+            [x, y, z] => Some(Vec3::new(
+                x.as_f64()? as f32,
+                y.as_f64()? as f32,
+                z.as_f64()? as f32,
+            )),
+            _ => None,
+        }
     }
 
-    fn draw(&self) {
-        self.vao.bind();
-        unsafe {
-            gl::DrawElements(
-                gl::TRIANGLES,
-                self.ebo.num_elements as i32,
-                self.ebo.element_type,
-                self.ebo.buffer_offset as *const GLvoid,
-            );
+    if !accessor.normalized() {
+        if let (Some(min), Some(max)) = (parse(accessor.min()), parse(accessor.max())) {
+            return Aabb::new(min, max);
         }
-        gl_check_error!();
+    }
+    match read_vec3(accessor, buffers) {
+        Some(points) => Aabb::from_points(&points),
+        None => Aabb::EMPTY,
+    }
+}
+
+/// Read a vec3 accessor of any component type as floats
+fn read_vec3(accessor: &gltf::Accessor, buffers: &[gltf::buffer::Data]) -> Option<Vec<Vec3>> {
+    fn read<T: Component>(
+        accessor: &gltf::Accessor,
+        buffers: &[gltf::buffer::Data],
+    ) -> Option<Vec<Vec3>> {
+        let normalized = accessor.normalized();
+        let iter = gltf::accessor::Iter::<[T; 3]>::new(accessor.clone(), |buffer| {
+            Some(&buffers[buffer.index()])
+        })?;
+        let values = iter
+            .map(|v| {
+                Vec3::new(
+                    v[0].to_f32(normalized),
+                    v[1].to_f32(normalized),
+                    v[2].to_f32(normalized),
+                )
+            })
+            .collect();
+        Some(values)
+    }
+
+    if accessor.dimensions() != Dimensions::Vec3 {
+        return None;
+    }
+    match accessor.data_type() {
+        DataType::F32 => read::<f32>(accessor, buffers),
+        DataType::I8 => read::<i8>(accessor, buffers),
+        DataType::U8 => read::<u8>(accessor, buffers),
+        DataType::I16 => read::<i16>(accessor, buffers),
+        DataType::U16 => read::<u16>(accessor, buffers),
+        DataType::U32 => None,
+    }
+}
+
+// ==================================== MorphTargets ==============================================
+
+/// Base vertices and per-target deltas, blended on the CPU into a dynamic vertex buffer
+#[derive(Debug)]
+struct MorphTargets {
+    /// Blended positions followed by blended normals
+    vbo: Buffer<Vec3>,
+
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>, // empty if the primitive has no normals
+    position_deltas: Vec<Vec<Vec3>>,
+    normal_deltas: Vec<Vec<Vec3>>,
+
+    /// Weights the buffer was last blended with
+    uploaded_weights: RefCell<Vec<f32>>,
+}
+
+impl MorphTargets {
+    /// None if the primitive has no targets
+    fn from_gltf(
+        mesh: &gltf::Mesh,
+        primitive: &gltf::Primitive,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Option<Self>, SceneError> {
+        if primitive.morph_targets().len() == 0 {
+            return Ok(None);
+        }
+        // Quantized data is converted to floats
+        let positions = primitive
+            .get(&Positions)
+            .and_then(|accessor| read_vec3(&accessor, buffers))
+            .ok_or(SceneError::MissingPositions {
+                mesh: mesh.index(),
+                primitive: primitive.index(),
+            })?;
+        let normals = primitive
+            .get(&Normals)
+            .and_then(|accessor| read_vec3(&accessor, buffers))
+            .unwrap_or_default();
+
+        // Targets without deltas for an attribute get zero deltas
+        let mut position_deltas = Vec::new();
+        let mut normal_deltas = Vec::new();
+        for (target_id, target) in primitive.morph_targets().enumerate() {
+            let read_deltas =
+                |semantic: gltf::Semantic, accessor: Option<gltf::Accessor>, len| match accessor {
+                    Some(accessor) => {
+                        read_vec3(&accessor, buffers).ok_or(SceneError::InvalidMorphTarget {
+                            mesh: mesh.index(),
+                            primitive: primitive.index(),
+                            target: target_id,
+                            semantic: format!("{:?}", semantic),
+                        })
+                    }
+                    None => Ok(vec![Vec3::ZERO; len]),
+                };
+            position_deltas.push(read_deltas(Positions, target.positions(), positions.len())?);
+            normal_deltas.push(read_deltas(Normals, target.normals(), normals.len())?);
+        }
+
+        let data: Vec<Vec3> = positions.iter().chain(normals.iter()).copied().collect();
+        let vbo = Buffer::new(&data, BufferUsage::Dynamic);
+
+        Ok(Some(MorphTargets {
+            vbo,
+            positions,
+            normals,
+            position_deltas,
+            normal_deltas,
+            uploaded_weights: RefCell::new(Vec::new()),
+        }))
+    }
+
+    /// Point the position and normal attributes of the vertex array to the blended buffer
+    fn set_attributes(&self, vao: &VertexArray) {
+        let mut layout =
+            VertexLayout::new(0).attribute(VertexAttribute::new::<Vec3>("POSITION", 0, 0));
+        if !self.normals.is_empty() {
+            let normals_offset = self.positions.len() * std::mem::size_of::<Vec3>();
+            layout = layout.attribute(VertexAttribute::new::<Vec3>("NORMAL", 1, normals_offset));
+        }
+        vao.set_layout(&self.vbo, &layout);
+    }
+
+    /// Blend the targets and upload the result, unless it's already there
+    fn apply(&self, weights: &[f32]) {
+        if *self.uploaded_weights.borrow() == weights {
+            return;
+        }
+
+        let blend = |base: &[Vec3], deltas: &[Vec<Vec3>]| -> Vec<Vec3> {
+            let mut result = base.to_vec();
+            for (deltas, &weight) in deltas.iter().zip(weights.iter()) {
+                if weight == 0.0 {
+                    continue;
+                }
+                for (value, delta) in result.iter_mut().zip(deltas.iter()) {
+                    *value += weight * *delta;
+                }
+            }
+            result
+        };
+        let mut data = blend(&self.positions, &self.position_deltas);
+        let mut normals = blend(&self.normals, &self.normal_deltas);
+        for normal in normals.iter_mut() {
+            *normal = normal.normalize_or_zero();
+        }
+        data.append(&mut normals);
+
+        self.vbo.update(0, &data);
+        *self.uploaded_weights.borrow_mut() = weights.to_vec();
     }
 }
@@ -1,52 +1,173 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::ffi::CString;
+use std::fmt::Display;
 use std::fs;
 use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::SystemTime;
 
 use gl::types::*;
-use glam::{Mat4, Vec3};
+use glam::{Mat3, Mat4, Vec2, Vec3, Vec4};
 use thiserror::Error;
 
+use crate::uniform_blocks::shared_block;
+use crate::utils::{track_gl_object_created, track_gl_object_deleted, GlObject};
+
 #[derive(Debug, Error)]
 pub enum ShaderError {
     #[error("I/O Error ({name}): {source}")]
     IoError { name: String, source: io::Error },
     #[error("Failed to compile shader {name}: {message}")]
     CompileError { name: String, message: String },
+    #[error("{name}:{line}: {message}")]
+    PreprocessError {
+        name: String,
+        line: usize,
+        message: String,
+    },
     #[error("Failed to link program: {0}")]
     LinkError(String),
-    #[error("Couldn't get uniform location for '{name}'")]
-    UniformLocationNotFound { name: String },
+    #[error("Uniform block {block}: {message}")]
+    UniformBlockError { block: String, message: String },
+    #[error("Uniform '{name}' is {found}, not {expected}")]
+    UniformTypeMismatch {
+        name: String,
+        expected: &'static str,
+        found: &'static str,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, ShaderError>;
 
 pub struct Program {
     id: GLuint,
+    /// Defines and version the stages are compiled with
+    defines: Defines,
+    /// Attached stages, compiled again by `reload_if_changed`
+    sources: Vec<ShaderSource>,
+    /// Active uniforms outside of uniform blocks, reflected when linking
+    uniforms: HashMap<String, Uniform>,
+    /// Missing uniforms that have already been warned about
+    warned: RefCell<HashSet<String>>,
+    /// Full names of struct members by prefix, so setting `Uniforms` doesn't allocate
+    member_names: RefCell<HashMap<String, HashMap<&'static str, Rc<str>>>>,
+}
+
+/// An active uniform as reflected by `glGetActiveUniform`
+#[derive(Clone, Copy, Debug)]
+pub struct Uniform {
+    pub location: GLint,
+    /// Number of array elements, 1 for non-arrays
+    pub size: GLint,
+    pub kind: GLenum,
+}
+
+struct ShaderSource {
+    kind: GLenum,
+    path: String,
+    /// The file and everything it includes, as of the last compilation
+    files: Vec<SourceFile>,
+    /// Keeps the compiled variant in the cache while the program is alive
+    _shader: Rc<Shader>,
 }
 
 impl Program {
     pub fn new() -> Self {
         let id = unsafe { gl::CreateProgram() };
-        Program { id }
+        track_gl_object_created(GlObject::Program, id);
+        Program {
+            id,
+            defines: Defines::default(),
+            sources: Vec::new(),
+            uniforms: HashMap::new(),
+            warned: RefCell::new(HashSet::new()),
+            member_names: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Adds `#define name` to the stages attached after this call
+    pub fn define(self, name: &str) -> Self {
+        self.define_value(name, "")
+    }
+
+    /// Adds `#define name value` to the stages attached after this call
+    pub fn define_value(mut self, name: &str, value: impl Display) -> Self {
+        self.defines = std::mem::take(&mut self.defines).define_value(name, value);
+        self
+    }
+
+    /// Replaces the `#version` of the stages attached after this call, e.g. "430 core"
+    pub fn version(mut self, version: &str) -> Self {
+        self.defines = std::mem::take(&mut self.defines).version(version);
+        self
     }
 
     pub fn vertex_shader(self, path: &str) -> Result<Self> {
-        let shader = Shader::new(gl::VERTEX_SHADER, path)?;
-        unsafe {
-            gl::AttachShader(self.id, shader.id());
-        }
-        Ok(self)
+        self.attach(gl::VERTEX_SHADER, path)
     }
 
     pub fn fragment_shader(self, path: &str) -> Result<Self> {
-        let shader = Shader::new(gl::FRAGMENT_SHADER, path)?;
+        self.attach(gl::FRAGMENT_SHADER, path)
+    }
+
+    fn attach(mut self, kind: GLenum, path: &str) -> Result<Self> {
+        let preprocessed = preprocess(path, &self.defines)?;
+        let shader = Shader::compile(kind, path, &preprocessed)?;
         unsafe {
             gl::AttachShader(self.id, shader.id());
         }
+        self.sources.push(ShaderSource {
+            kind,
+            path: path.to_owned(),
+            files: preprocessed.files,
+            _shader: shader,
+        });
         Ok(self)
     }
 
-    pub fn link(self) -> Result<Self> {
+    /// Paths of the attached shader sources
+    pub fn source_paths(&self) -> Vec<&str> {
+        self.sources
+            .iter()
+            .map(|source| source.path.as_str())
+            .collect()
+    }
+
+    /// Compile and link the program again if any of its source files changed.
+    /// Uniform values are carried over to the new program. If it fails to build,
+    /// the current program stays in use. Returns whether the program was replaced
+    pub fn reload_if_changed(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for file in self
+            .sources
+            .iter_mut()
+            .flat_map(|source| source.files.iter_mut())
+        {
+            let modified = file_modified(&file.path);
+            if modified != file.modified {
+                // Remembered even if the build fails, so it's retried after the next change
+                file.modified = modified;
+                changed = true;
+            }
+        }
+        if !changed {
+            return Ok(false);
+        }
+
+        let mut program = Program::new();
+        program.defines = self.defines.clone();
+        for source in self.sources.iter() {
+            program = program.attach(source.kind, &source.path)?;
+        }
+        let program = program.link()?;
+        copy_uniforms(self, &program);
+        *self = program;
+        Ok(true)
+    }
+
+    pub fn link(mut self) -> Result<Self> {
         unsafe {
             gl::LinkProgram(self.id);
         }
@@ -71,6 +192,8 @@ impl Program {
             return Err(ShaderError::LinkError(error.to_string_lossy().into_owned()));
         }
 
+        bind_uniform_blocks(self.id)?;
+        self.uniforms = reflect_uniforms(self.id);
         Ok(self)
     }
 
@@ -80,84 +203,605 @@ impl Program {
         }
     }
 
-    pub fn get_uniform_location(&self, name: &str) -> Result<GLint> {
-        let name_cstr = CString::new(name).unwrap();
-        let location =
-            unsafe { gl::GetUniformLocation(self.id, name_cstr.as_ptr() as *const GLchar) };
-        if location < 0 {
-            return Err(ShaderError::UniformLocationNotFound {
+    /// Reflected uniform by name. Arrays are found by their name without "[0]"
+    pub fn uniform(&self, name: &str) -> Option<&Uniform> {
+        self.uniforms.get(name)
+    }
+
+    /// Names of all reflected uniforms
+    pub fn uniform_names(&self) -> impl Iterator<Item = &str> {
+        self.uniforms.keys().map(String::as_str)
+    }
+
+    /// Location of a uniform, which may be an array element like "joint_matrices[3]",
+    /// and the number of array elements from there on. Missing uniforms are
+    /// warned about once and give None, uniforms of another type are an error
+    fn uniform_location(&self, name: &str, expected: &[GLenum]) -> Result<Option<(GLint, GLint)>> {
+        let found = match self.uniforms.get(name) {
+            Some(uniform) => Some((uniform, 0)),
+            None => split_array_index(name).and_then(|(array, index)| {
+                self.uniforms
+                    .get(array)
+                    .filter(|uniform| index < uniform.size)
+                    .map(|uniform| (uniform, index))
+            }),
+        };
+        let (uniform, index) = match found {
+            Some(found) => found,
+            None => {
+                self.warn_missing_uniform(name);
+                return Ok(None);
+            }
+        };
+        if !expected.contains(&uniform.kind) {
+            return Err(ShaderError::UniformTypeMismatch {
                 name: name.to_owned(),
+                expected: gl_type_name(expected[0]),
+                found: gl_type_name(uniform.kind),
             });
         }
-        Ok(location)
+        Ok(Some((uniform.location + index, uniform.size - index)))
+    }
+
+    fn warn_missing_uniform(&self, name: &str) {
+        if !self.warned.borrow_mut().insert(name.to_owned()) {
+            return;
+        }
+        let mut candidates: Vec<(usize, &str)> = self
+            .uniform_names()
+            .map(|candidate| (edit_distance(name, candidate), candidate))
+            .filter(|&(distance, _)| distance <= (name.len() / 3).max(3))
+            .collect();
+        candidates.sort();
+        let candidates: Vec<&str> = candidates.iter().take(3).map(|&(_, c)| c).collect();
+        let hint = if candidates.is_empty() {
+            String::new()
+        } else {
+            format!(", did you mean {}?", candidates.join(", "))
+        };
+        eprintln!(
+            "Warning: uniform '{}' is not active in {} (misspelled or optimized out){}",
+            name,
+            self.source_paths().join(" + "),
+            hint
+        );
+    }
+
+    /// Name of a member of the uniform struct `prefix`, or a top-level uniform if it's empty.
+    /// Built once per prefix and member, later calls only clone the `Rc`
+    pub fn member_name(&self, prefix: &str, member: &'static str) -> Rc<str> {
+        if let Some(name) = self
+            .member_names
+            .borrow()
+            .get(prefix)
+            .and_then(|names| names.get(member))
+        {
+            return Rc::clone(name);
+        }
+        let name: Rc<str> = if prefix.is_empty() {
+            member.into()
+        } else {
+            format!("{}.{}", prefix, member).into()
+        };
+        self.member_names
+            .borrow_mut()
+            .entry(prefix.to_owned())
+            .or_default()
+            .insert(member, Rc::clone(&name));
+        name
+    }
+
+    /// Sets the fields of a `#[derive(Uniforms)]` struct as the members of the
+    /// uniform struct `name`, e.g. "material". Pass "" for top-level uniforms
+    pub fn set_uniforms<T: Uniforms>(&self, name: &str, uniforms: &T) -> Result<()> {
+        uniforms.set_uniforms(self, name)
     }
 
     /// Assigns a name from the shader to a texture unit
     pub fn set_texture_unit(&self, name: &str, unit: i32) -> Result<()> {
-        let location = self.get_uniform_location(name)?;
-        unsafe {
-            gl::Uniform1i(location, unit);
+        if let Some((location, _)) = self.uniform_location(name, SAMPLER_TYPES)? {
+            unsafe {
+                gl::Uniform1i(location, unit);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets a bool uniform
+    pub fn set_bool(&self, name: &str, value: bool) -> Result<()> {
+        if let Some((location, _)) = self.uniform_location(name, &[gl::BOOL])? {
+            unsafe {
+                gl::Uniform1i(location, value as GLint);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets an int uniform
+    pub fn set_int(&self, name: &str, value: i32) -> Result<()> {
+        if let Some((location, _)) = self.uniform_location(name, &[gl::INT])? {
+            unsafe {
+                gl::Uniform1i(location, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets a float uniform
+    pub fn set_float(&self, name: &str, value: f32) -> Result<()> {
+        if let Some((location, _)) = self.uniform_location(name, &[gl::FLOAT])? {
+            unsafe {
+                gl::Uniform1f(location, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets a vec2 uniform
+    pub fn set_vec2(&self, name: &str, vec: &Vec2) -> Result<()> {
+        if let Some((location, _)) = self.uniform_location(name, &[gl::FLOAT_VEC2])? {
+            unsafe {
+                gl::Uniform2fv(location, 1, vec.to_array().as_ptr());
+            }
         }
         Ok(())
     }
 
     /// Sets a vec3 uniform
     pub fn set_vec3(&self, name: &str, vec: &Vec3) -> Result<()> {
-        let location = self.get_uniform_location(name)?;
-        unsafe {
-            gl::Uniform3fv(location, 1, vec.to_array().as_ptr());
+        if let Some((location, _)) = self.uniform_location(name, &[gl::FLOAT_VEC3])? {
+            unsafe {
+                gl::Uniform3fv(location, 1, vec.to_array().as_ptr());
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets a vec4 uniform
+    pub fn set_vec4(&self, name: &str, vec: &Vec4) -> Result<()> {
+        if let Some((location, _)) = self.uniform_location(name, &[gl::FLOAT_VEC4])? {
+            unsafe {
+                gl::Uniform4fv(location, 1, vec.to_array().as_ptr());
+            }
         }
         Ok(())
     }
 
-    /// Sets a [f32; 3] uniform
+    #[allow(dead_code)]
+    /// Sets a vec3 uniform from the first 3 floats of a slice
     pub fn set_float3(&self, name: &str, vec: &[f32]) -> Result<()> {
-        let location = self.get_uniform_location(name)?;
-        unsafe {
-            gl::Uniform3fv(location, 1, vec.as_ptr());
+        self.set_vec3(name, &Vec3::from_slice(vec))
+    }
+
+    /// Sets a mat3 uniform
+    pub fn set_mat3(&self, name: &str, mat: &Mat3) -> Result<()> {
+        if let Some((location, _)) = self.uniform_location(name, &[gl::FLOAT_MAT3])? {
+            unsafe {
+                gl::UniformMatrix3fv(location, 1, gl::FALSE, mat.to_cols_array().as_ptr());
+            }
         }
         Ok(())
     }
 
     /// Sets a mat4 uniform
     pub fn set_mat4(&self, name: &str, mat: &Mat4) -> Result<()> {
-        let location = self.get_uniform_location(name)?;
-        unsafe {
-            gl::UniformMatrix4fv(location, 1, gl::FALSE, mat.to_cols_array().as_ptr());
+        if let Some((location, _)) = self.uniform_location(name, &[gl::FLOAT_MAT4])? {
+            unsafe {
+                gl::UniformMatrix4fv(location, 1, gl::FALSE, mat.to_cols_array().as_ptr());
+            }
         }
         Ok(())
     }
 
-    /// Sets a float uniform
-    pub fn set_float(&self, name: &str, value: f32) -> Result<()> {
-        let location = self.get_uniform_location(name)?;
-        unsafe {
-            gl::Uniform1fv(location, 1, &value as *const f32);
+    /// Sets an int[] uniform. Values past the end of the array are ignored
+    pub fn set_int_array(&self, name: &str, values: &[i32]) -> Result<()> {
+        if let Some((location, size)) = self.uniform_location(name, &[gl::INT])? {
+            let count = values.len().min(size as usize);
+            unsafe {
+                gl::Uniform1iv(location, count as GLsizei, values.as_ptr());
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets a float[] uniform. Values past the end of the array are ignored
+    pub fn set_float_array(&self, name: &str, values: &[f32]) -> Result<()> {
+        if let Some((location, size)) = self.uniform_location(name, &[gl::FLOAT])? {
+            let count = values.len().min(size as usize);
+            unsafe {
+                gl::Uniform1fv(location, count as GLsizei, values.as_ptr());
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets a vec3[] uniform. Values past the end of the array are ignored
+    pub fn set_vec3_array(&self, name: &str, vecs: &[Vec3]) -> Result<()> {
+        if let Some((location, size)) = self.uniform_location(name, &[gl::FLOAT_VEC3])? {
+            let count = vecs.len().min(size as usize);
+            unsafe {
+                gl::Uniform3fv(location, count as GLsizei, vecs.as_ptr() as *const f32);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets a vec4[] uniform. Values past the end of the array are ignored
+    pub fn set_vec4_array(&self, name: &str, vecs: &[Vec4]) -> Result<()> {
+        if let Some((location, size)) = self.uniform_location(name, &[gl::FLOAT_VEC4])? {
+            let count = vecs.len().min(size as usize);
+            unsafe {
+                gl::Uniform4fv(location, count as GLsizei, vecs.as_ptr() as *const f32);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets a mat4[] uniform. Values past the end of the array are ignored
+    pub fn set_mat4_array(&self, name: &str, mats: &[Mat4]) -> Result<()> {
+        if let Some((location, size)) = self.uniform_location(name, &[gl::FLOAT_MAT4])? {
+            let count = mats.len().min(size as usize);
+            unsafe {
+                gl::UniformMatrix4fv(
+                    location,
+                    count as GLsizei,
+                    gl::FALSE,
+                    mats.as_ptr() as *const f32,
+                );
+            }
         }
         Ok(())
     }
 }
 
+/// Structs whose fields are set as uniforms, see `gl_derive::Uniforms`
+pub trait Uniforms {
+    /// Sets every field as a uniform named `prefix.field`
+    fn set_uniforms(&self, program: &Program, prefix: &str) -> Result<()>;
+}
+
+/// Values which can be set as a uniform of the matching GLSL type
+pub trait UniformValue {
+    fn set_uniform(&self, program: &Program, name: &str) -> Result<()>;
+}
+
+macro_rules! impl_uniform_value {
+    ($($ty:ty => $setter:ident),* $(,)?) => {
+        $(
+            impl UniformValue for $ty {
+                fn set_uniform(&self, program: &Program, name: &str) -> Result<()> {
+                    program.$setter(name, *self)
+                }
+            }
+        )*
+    };
+}
+
+impl_uniform_value!(f32 => set_float, i32 => set_int, bool => set_bool);
+
+macro_rules! impl_uniform_value_ref {
+    ($($ty:ty => $setter:ident),* $(,)?) => {
+        $(
+            impl UniformValue for $ty {
+                fn set_uniform(&self, program: &Program, name: &str) -> Result<()> {
+                    program.$setter(name, self)
+                }
+            }
+        )*
+    };
+}
+
+impl_uniform_value_ref!(
+    Vec2 => set_vec2,
+    Vec3 => set_vec3,
+    Vec4 => set_vec4,
+    Mat3 => set_mat3,
+    Mat4 => set_mat4,
+    [f32] => set_float_array,
+    [i32] => set_int_array,
+    [Vec3] => set_vec3_array,
+    [Vec4] => set_vec4_array,
+    [Mat4] => set_mat4_array,
+);
+
+impl<T> UniformValue for Vec<T>
+where
+    [T]: UniformValue,
+{
+    fn set_uniform(&self, program: &Program, name: &str) -> Result<()> {
+        self.as_slice().set_uniform(program, name)
+    }
+}
+
+/// Checks the active uniform blocks of a linked program against the Rust side
+/// layouts in `uniform_blocks` and binds them to their binding points
+fn bind_uniform_blocks(program: GLuint) -> Result<()> {
+    let mut count: GLint = 0;
+    unsafe {
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_BLOCKS, &mut count);
+    }
+    for block in 0..count as GLuint {
+        let name = gl_name(|max_length, length, name| unsafe {
+            gl::GetActiveUniformBlockName(program, block, max_length, length, name)
+        });
+        let error = |message: String| ShaderError::UniformBlockError {
+            block: name.clone(),
+            message,
+        };
+        let layout = shared_block(&name)
+            .ok_or_else(|| error("not declared in uniform_blocks.rs".to_owned()))?;
+
+        let (mut size, mut member_count): (GLint, GLint) = (0, 0);
+        unsafe {
+            gl::GetActiveUniformBlockiv(program, block, gl::UNIFORM_BLOCK_DATA_SIZE, &mut size);
+            gl::GetActiveUniformBlockiv(
+                program,
+                block,
+                gl::UNIFORM_BLOCK_ACTIVE_UNIFORMS,
+                &mut member_count,
+            );
+        }
+        if size as usize != layout.size {
+            return Err(error(format!(
+                "{} bytes in the shader, {} in Rust",
+                size, layout.size
+            )));
+        }
+
+        let mut members = vec![0 as GLint; member_count as usize];
+        let mut offsets = vec![0 as GLint; member_count as usize];
+        unsafe {
+            gl::GetActiveUniformBlockiv(
+                program,
+                block,
+                gl::UNIFORM_BLOCK_ACTIVE_UNIFORM_INDICES,
+                members.as_mut_ptr(),
+            );
+            gl::GetActiveUniformsiv(
+                program,
+                member_count,
+                members.as_ptr() as *const GLuint,
+                gl::UNIFORM_OFFSET,
+                offsets.as_mut_ptr(),
+            );
+        }
+        for (&member, &offset) in members.iter().zip(offsets.iter()) {
+            let member_name = gl_name(|max_length, length, name| unsafe {
+                gl::GetActiveUniformName(program, member as GLuint, max_length, length, name)
+            });
+            // Members of blocks with an instance name are reported as "Block.member"
+            let prefix = format!("{}.", name);
+            let short_name = member_name.strip_prefix(&prefix).unwrap_or(&member_name);
+            let short_name = short_name.strip_suffix("[0]").unwrap_or(short_name);
+            match layout.offset(short_name) {
+                Some(rust_offset) if rust_offset == offset as usize => {}
+                Some(rust_offset) => {
+                    return Err(error(format!(
+                        "'{}' is at offset {} in the shader, {} in Rust",
+                        short_name, offset, rust_offset
+                    )))
+                }
+                None => return Err(error(format!("'{}' is missing in Rust", short_name))),
+            }
+        }
+
+        unsafe {
+            gl::UniformBlockBinding(program, block, layout.binding);
+        }
+    }
+    Ok(())
+}
+
+/// Reads a name from a GL getter taking (max length, &mut length, buffer)
+fn gl_name(get: impl Fn(GLsizei, &mut GLsizei, *mut GLchar)) -> String {
+    let mut name = vec![0u8; 256];
+    let mut length: GLsizei = 0;
+    get(
+        name.len() as GLsizei,
+        &mut length,
+        name.as_mut_ptr() as *mut GLchar,
+    );
+    name.truncate(length as usize);
+    String::from_utf8_lossy(&name).into_owned()
+}
+
+const SAMPLER_TYPES: &[GLenum] = &[
+    gl::SAMPLER_2D,
+    gl::SAMPLER_3D,
+    gl::SAMPLER_CUBE,
+    gl::SAMPLER_2D_ARRAY,
+    gl::SAMPLER_2D_SHADOW,
+];
+
+/// Active uniforms of a linked program outside of uniform blocks, arrays by their
+/// name without "[0]"
+fn reflect_uniforms(program: GLuint) -> HashMap<String, Uniform> {
+    let (mut count, mut max_length): (GLint, GLint) = (0, 0);
+    unsafe {
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut count);
+        gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_length);
+    }
+    let mut uniforms = HashMap::new();
+    for index in 0..count as GLuint {
+        let mut name = vec![0u8; max_length.max(1) as usize];
+        let (mut length, mut size, mut kind): (GLsizei, GLint, GLenum) = (0, 0, 0);
+        unsafe {
+            gl::GetActiveUniform(
+                program,
+                index,
+                max_length,
+                &mut length,
+                &mut size,
+                &mut kind,
+                name.as_mut_ptr() as *mut GLchar,
+            );
+        }
+        name.truncate(length as usize);
+        let name = String::from_utf8_lossy(&name).into_owned();
+        let name_cstr = CString::new(name.as_str()).unwrap();
+        let location = unsafe { gl::GetUniformLocation(program, name_cstr.as_ptr()) };
+        if location < 0 {
+            continue; // in a uniform block
+        }
+        let name = name.strip_suffix("[0]").unwrap_or(&name).to_owned();
+        uniforms.insert(
+            name,
+            Uniform {
+                location,
+                size,
+                kind,
+            },
+        );
+    }
+    uniforms
+}
+
+/// Splits "name[index]" into "name" and the index
+fn split_array_index(name: &str) -> Option<(&str, GLint)> {
+    let (array, index) = name.strip_suffix(']')?.rsplit_once('[')?;
+    Some((array, index.parse().ok()?))
+}
+
+/// Levenshtein distance, for suggesting uniform names
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = diagonal + (a_char != b_char) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+fn gl_type_name(kind: GLenum) -> &'static str {
+    match kind {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::INT => "int",
+        gl::INT_VEC2 => "ivec2",
+        gl::INT_VEC3 => "ivec3",
+        gl::INT_VEC4 => "ivec4",
+        gl::UNSIGNED_INT => "uint",
+        gl::BOOL => "bool",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::SAMPLER_3D => "sampler3D",
+        gl::SAMPLER_CUBE => "samplerCube",
+        gl::SAMPLER_2D_ARRAY => "sampler2DArray",
+        gl::SAMPLER_2D_SHADOW => "sampler2DShadow",
+        _ => "an unsupported type",
+    }
+}
+
+/// Set the uniforms of `to` to their values in `from`, where both have them with the same type
+fn copy_uniforms(from: &Program, to: &Program) {
+    to.set_used();
+    for (name, from_uniform) in from.uniforms.iter() {
+        let to_uniform = match to.uniforms.get(name) {
+            Some(to_uniform) if to_uniform.kind == from_uniform.kind => to_uniform,
+            _ => continue,
+        };
+        for element in 0..to_uniform.size.min(from_uniform.size) {
+            // Locations of array elements are consecutive
+            let from_location = from_uniform.location + element;
+            let to_location = to_uniform.location + element;
+            let mut floats = [0.0f32; 16];
+            let mut int: GLint = 0;
+            unsafe {
+                match from_uniform.kind {
+                    gl::FLOAT
+                    | gl::FLOAT_VEC2
+                    | gl::FLOAT_VEC3
+                    | gl::FLOAT_VEC4
+                    | gl::FLOAT_MAT3
+                    | gl::FLOAT_MAT4 => {
+                        gl::GetUniformfv(from.id, from_location, floats.as_mut_ptr());
+                    }
+                    _ => gl::GetUniformiv(from.id, from_location, &mut int),
+                }
+                match from_uniform.kind {
+                    gl::FLOAT => gl::Uniform1fv(to_location, 1, floats.as_ptr()),
+                    gl::FLOAT_VEC2 => gl::Uniform2fv(to_location, 1, floats.as_ptr()),
+                    gl::FLOAT_VEC3 => gl::Uniform3fv(to_location, 1, floats.as_ptr()),
+                    gl::FLOAT_VEC4 => gl::Uniform4fv(to_location, 1, floats.as_ptr()),
+                    gl::FLOAT_MAT3 => {
+                        gl::UniformMatrix3fv(to_location, 1, gl::FALSE, floats.as_ptr())
+                    }
+                    gl::FLOAT_MAT4 => {
+                        gl::UniformMatrix4fv(to_location, 1, gl::FALSE, floats.as_ptr())
+                    }
+                    gl::INT
+                    | gl::BOOL
+                    | gl::SAMPLER_2D
+                    | gl::SAMPLER_3D
+                    | gl::SAMPLER_CUBE
+                    | gl::SAMPLER_2D_ARRAY
+                    | gl::SAMPLER_2D_SHADOW => gl::Uniform1i(to_location, int),
+                    _ => {} // not used by our shaders
+                }
+            }
+        }
+    }
+}
+
+fn file_modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
 impl Drop for Program {
     fn drop(&mut self) {
         unsafe {
             gl::DeleteProgram(self.id);
         }
+        track_gl_object_deleted(GlObject::Program, self.id);
     }
 }
 
-struct Shader {
+thread_local! {
+    /// Compiled shader objects by stage and preprocessed source, so that programs
+    /// using the same variant of a stage share it
+    static SHADER_CACHE: RefCell<HashMap<(GLenum, String), Weak<Shader>>> =
+        RefCell::new(HashMap::new());
+}
+
+pub struct Shader {
     id: GLuint,
 }
 
 impl Shader {
-    pub fn new(kind: GLenum, path: &str) -> Result<Self> {
-        let source = fs::read_to_string(path).map_err(|e| ShaderError::IoError {
-            name: path.to_owned(),
-            source: e,
-        })?;
-        let source = CString::new(source).unwrap();
+    #[allow(dead_code)]
+    /// Preprocesses the file with `defines` and compiles it, or returns the
+    /// already compiled variant
+    pub fn new(kind: GLenum, path: &str, defines: &Defines) -> Result<Rc<Self>> {
+        Shader::compile(kind, path, &preprocess(path, defines)?)
+    }
+
+    fn compile(kind: GLenum, path: &str, preprocessed: &Preprocessed) -> Result<Rc<Self>> {
+        let key = (kind, preprocessed.source.clone());
+        let cached = SHADER_CACHE.with(|cache| cache.borrow().get(&key).and_then(Weak::upgrade));
+        if let Some(shader) = cached {
+            return Ok(shader);
+        }
+
+        let source =
+            CString::new(preprocessed.source.as_str()).map_err(|_| ShaderError::CompileError {
+                name: path.to_owned(),
+                message: "source contains a nul byte".to_owned(),
+            })?;
         let id = unsafe { gl::CreateShader(kind) };
+        track_gl_object_created(GlObject::Shader, id);
+        // Owned from here on, so it's deleted on errors too
+        let shader = Shader { id };
         unsafe {
             gl::ShaderSource(id, 1, &source.as_ptr(), std::ptr::null());
             gl::CompileShader(id);
@@ -177,10 +821,17 @@ impl Shader {
             }
             return Err(ShaderError::CompileError {
                 name: path.to_owned(),
-                message: error.to_string_lossy().into_owned(),
+                message: preprocessed.map_log(&error.to_string_lossy()),
             });
         }
-        Ok(Shader { id })
+
+        let shader = Rc::new(shader);
+        SHADER_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            cache.retain(|_, shader| shader.strong_count() > 0);
+            cache.insert(key, Rc::downgrade(&shader));
+        });
+        Ok(shader)
     }
 
     pub fn id(&self) -> GLuint {
@@ -193,7 +844,190 @@ impl Drop for Shader {
         unsafe {
             gl::DeleteShader(self.id);
         }
+        track_gl_object_deleted(GlObject::Shader, self.id);
+    }
+}
+
+// ==================================== Preprocessor ==============================================
+
+/// Defines and `#version` injected into shader sources
+#[derive(Clone, Debug, Default)]
+pub struct Defines {
+    version: Option<String>,
+    values: Vec<(String, String)>,
+}
+
+impl Defines {
+    pub fn define_value(mut self, name: &str, value: impl Display) -> Self {
+        self.values.push((name.to_owned(), value.to_string()));
+        self
+    }
+
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_owned());
+        self
+    }
+}
+
+struct SourceFile {
+    path: String,
+    /// Modification time of the file when it was read
+    modified: Option<SystemTime>,
+}
+
+/// Shader source with includes resolved and defines injected
+struct Preprocessed {
+    source: String,
+    /// Index into `files` and line number of every line of `source`
+    lines: Vec<(usize, usize)>,
+    /// The root file first, then the included ones
+    files: Vec<SourceFile>,
+}
+
+impl Preprocessed {
+    /// Points references to lines of the source in a driver's info log at the
+    /// original files. Understands "0(12)" (Nvidia) and "0:12" (Mesa, AMD)
+    fn map_log(&self, log: &str) -> String {
+        let mut mapped = String::with_capacity(log.len());
+        for log_line in log.lines() {
+            match find_log_location(log_line) {
+                Some((start, end, line)) if line > 0 && line <= self.lines.len() => {
+                    let (file, original_line) = self.lines[line - 1];
+                    mapped.push_str(&log_line[..start]);
+                    mapped.push_str(&format!("{}:{}", self.files[file].path, original_line));
+                    mapped.push_str(&log_line[end..]);
+                }
+                _ => mapped.push_str(log_line),
+            }
+            mapped.push('\n');
+        }
+        mapped
+    }
+}
+
+/// Byte range and line number of the first "0(line)" or "0:line" in a log line
+fn find_log_location(log_line: &str) -> Option<(usize, usize, usize)> {
+    let bytes = log_line.as_bytes();
+    for start in 0..bytes.len() {
+        if bytes[start] != b'0' || (start > 0 && bytes[start - 1].is_ascii_alphanumeric()) {
+            continue;
+        }
+        let separator = match bytes.get(start + 1) {
+            Some(&separator) if separator == b'(' || separator == b':' => separator,
+            _ => continue,
+        };
+        let digits_start = start + 2;
+        let digits_end = digits_start
+            + bytes[digits_start..]
+                .iter()
+                .take_while(|byte| byte.is_ascii_digit())
+                .count();
+        if digits_end == digits_start {
+            continue;
+        }
+        let end = match separator {
+            b'(' if bytes.get(digits_end) == Some(&b')') => digits_end + 1,
+            b'(' => continue,
+            _ => digits_end,
+        };
+        let line = log_line[digits_start..digits_end].parse().ok()?;
+        return Some((start, end, line));
+    }
+    None
+}
+
+/// Reads a shader, resolving `#include "file"` relative to the including file
+/// (each file is included once) and adding `defines` after the `#version` line
+fn preprocess(path: &str, defines: &Defines) -> Result<Preprocessed> {
+    let mut preprocessed = Preprocessed {
+        source: String::new(),
+        lines: Vec::new(),
+        files: Vec::new(),
+    };
+    include_file(&mut preprocessed, Path::new(path), defines)?;
+    Ok(preprocessed)
+}
+
+fn include_file(out: &mut Preprocessed, path: &Path, defines: &Defines) -> Result<()> {
+    let path = normalize_path(path);
+    let name = path.to_string_lossy().into_owned();
+    if out.files.iter().any(|file| file.path == name) {
+        return Ok(());
+    }
+    let modified = file_modified(&name);
+    let text = fs::read_to_string(&path).map_err(|e| ShaderError::IoError {
+        name: name.clone(),
+        source: e,
+    })?;
+    let file = out.files.len();
+    let is_root = file == 0;
+    out.files.push(SourceFile {
+        path: name.clone(),
+        modified,
+    });
+
+    let push_line = |out: &mut Preprocessed, text: &str, line: usize| {
+        out.source.push_str(text);
+        out.source.push('\n');
+        out.lines.push((file, line));
+    };
+    let mut has_version = false;
+    for (index, text) in text.lines().enumerate() {
+        let line = index + 1;
+        let directive = text.trim_start();
+        if is_root && !has_version && directive.starts_with("#version") {
+            has_version = true;
+            match &defines.version {
+                Some(version) => push_line(out, &format!("#version {}", version), line),
+                None => push_line(out, text, line),
+            }
+            for (name, value) in defines.values.iter() {
+                push_line(out, &format!("#define {} {}", name, value), line);
+            }
+        } else if let Some(rest) = directive.strip_prefix("#include") {
+            let include = rest
+                .trim()
+                .strip_prefix('"')
+                .and_then(|rest| rest.strip_suffix('"'))
+                .ok_or_else(|| ShaderError::PreprocessError {
+                    name: name.clone(),
+                    line,
+                    message: format!("expected #include \"file\", found '{}'", directive),
+                })?;
+            let include_path = path.parent().unwrap_or(Path::new("")).join(include);
+            include_file(out, &include_path, defines)?;
+        } else {
+            push_line(out, text, line);
+        }
+    }
+    if is_root && !has_version {
+        return Err(ShaderError::PreprocessError {
+            name,
+            line: 1,
+            message: "missing #version".to_owned(),
+        });
+    }
+    Ok(())
+}
+
+/// Removes "." and "dir/.." from a path so every file has one name
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir
+                if matches!(
+                    normalized.components().next_back(),
+                    Some(Component::Normal(_))
+                ) =>
+            {
+                normalized.pop();
+            }
+            _ => normalized.push(component),
+        }
     }
+    normalized
 }
 
 fn new_cstring(len: usize) -> CString {
@@ -1,10 +1,10 @@
-use gl::types::*;
-use glam::Mat4;
+use bytemuck::{Pod, Zeroable};
+use gl_derive::Vertex;
 use thiserror::Error;
 
-use crate::buffers::{Buffer, VertexArray};
+use crate::buffers::{Buffer, BufferUsage, Vertex, VertexArray};
 use crate::shader::{Program, ShaderError};
-use crate::texture::{load_image, TextureError};
+use crate::texture::{Texture, TextureError};
 
 #[derive(Debug, Error)]
 pub enum SkyboxError {
@@ -14,66 +14,27 @@ pub enum SkyboxError {
     Shader(#[from] ShaderError),
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable, Vertex)]
+struct SkyboxVertex {
+    #[vertex(location = 0)]
+    position: [f32; 3],
+}
+
 pub struct Skybox {
-    id: GLuint,
+    texture: Texture,
     shader: Program,
     vao: VertexArray,
+    /// Only owned so the vertex array's data outlives it
+    _vbo: Buffer<SkyboxVertex>,
 }
 
 impl Skybox {
     /// right, left, top, bottom, front, back
     pub fn from(paths: [&str; 6]) -> Result<Self, SkyboxError> {
-        // Generate texture
-        let mut id: GLuint = 0;
-        unsafe {
-            gl::GenTextures(1, &mut id);
-            gl::BindTexture(gl::TEXTURE_CUBE_MAP, id);
-
-            gl::TexParameteri(
-                gl::TEXTURE_CUBE_MAP,
-                gl::TEXTURE_WRAP_S,
-                gl::CLAMP_TO_EDGE as GLint,
-            );
-            gl::TexParameteri(
-                gl::TEXTURE_CUBE_MAP,
-                gl::TEXTURE_WRAP_T,
-                gl::CLAMP_TO_EDGE as GLint,
-            );
-            gl::TexParameteri(
-                gl::TEXTURE_CUBE_MAP,
-                gl::TEXTURE_WRAP_R,
-                gl::CLAMP_TO_EDGE as GLint,
-            );
-            gl::TexParameteri(
-                gl::TEXTURE_CUBE_MAP,
-                gl::TEXTURE_MIN_FILTER,
-                gl::LINEAR as GLint,
-            );
-            gl::TexParameteri(
-                gl::TEXTURE_CUBE_MAP,
-                gl::TEXTURE_MAG_FILTER,
-                gl::LINEAR as GLint,
-            );
-        }
-
-        // Load images
-        for (i, path) in paths.iter().enumerate() {
-            let img = load_image(path, false)?;
-            unsafe {
-                // Send to GPU
-                gl::TexImage2D(
-                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as u32,
-                    0,
-                    gl::SRGB as GLint,
-                    img.width as GLint,
-                    img.height as GLint,
-                    0,
-                    gl::RGB,
-                    gl::UNSIGNED_BYTE,
-                    img.data.as_ptr() as *const std::ffi::c_void,
-                );
-            }
-        }
+        let texture = Texture::new_cube_map()
+            .set_cube_map_parameters()
+            .set_cube_map_images(paths)?;
 
         // Create shader
         let shader = Program::new()
@@ -129,39 +90,33 @@ impl Skybox {
             1.0, -1.0,  1.0,
         ];
         let vao = VertexArray::new();
-        vao.bind();
-        let vbo = Buffer::create(
-            vertices.as_ptr() as *const u8,
-            vertices.len() * std::mem::size_of::<f32>(),
-        );
-        vbo.bind_as_array_buffer();
-        unsafe {
-            gl::VertexAttribPointer(
-                0,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                0,
-                std::ptr::null() as *const GLvoid,
-            );
-            gl::EnableVertexAttribArray(0);
-        }
+        let vbo: Buffer<SkyboxVertex> =
+            Buffer::new(bytemuck::cast_slice(&vertices), BufferUsage::Static);
+        vao.set_layout(&vbo, &SkyboxVertex::layout());
         vao.unbind();
 
-        Ok(Skybox { id, shader, vao })
+        Ok(Skybox {
+            texture,
+            shader,
+            vao,
+            _vbo: vbo,
+        })
+    }
+
+    pub fn shader_mut(&mut self) -> &mut Program {
+        &mut self.shader
     }
 
-    pub fn draw(&self, proj: &Mat4, view: &Mat4) -> Result<(), SkyboxError> {
+    /// Draws with the camera from the Camera uniform block
+    pub fn draw(&self) -> Result<(), SkyboxError> {
         unsafe {
             gl::DepthFunc(gl::LEQUAL);
         }
         self.shader.set_used();
-        self.shader.set_mat4("proj", proj)?;
-        self.shader.set_mat4("view", view)?;
         self.vao.bind();
 
+        self.texture.bind(0);
         unsafe {
-            gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.id);
             gl::DrawArrays(gl::TRIANGLES, 0, 36);
             gl::DepthFunc(gl::LESS);
         }
@@ -2,6 +2,8 @@ use gl::types::*;
 use stb_image::image::{self, Image, LoadResult};
 use thiserror::Error;
 
+use crate::utils::{gl_supports_dsa, track_gl_object_created, track_gl_object_deleted, GlObject};
+
 #[derive(Debug, Error)]
 pub enum TextureError {
     #[error("Image format F32 is not supported")]
@@ -10,23 +12,46 @@ pub enum TextureError {
     LoadError(String),
 }
 
+#[derive(Debug)]
 pub struct Texture {
     id: GLuint,
+    /// TEXTURE_2D or TEXTURE_CUBE_MAP
+    target: GLenum,
 }
 
+/// (internal format, pixel format, component type) of uploaded pixels
+type PixelFormat = (GLenum, GLenum, GLenum);
+
 impl Texture {
     pub fn new() -> Self {
+        Texture::create(gl::TEXTURE_2D)
+    }
+
+    pub fn new_cube_map() -> Self {
+        Texture::create(gl::TEXTURE_CUBE_MAP)
+    }
+
+    fn create(target: GLenum) -> Self {
         let mut id: GLuint = 0;
         unsafe {
-            gl::GenTextures(1, &mut id);
+            if gl_supports_dsa() {
+                gl::CreateTextures(target, 1, &mut id);
+            } else {
+                gl::GenTextures(1, &mut id);
+            }
         }
-        Texture { id }
+        track_gl_object_created(GlObject::Texture, id);
+        Texture { id, target }
     }
 
-    pub fn bind_2d(&self, unit: i32) {
+    pub fn bind(&self, unit: i32) {
         unsafe {
-            gl::ActiveTexture(Texture::unit_to_gl_const(unit));
-            gl::BindTexture(gl::TEXTURE_2D, self.id);
+            if gl_supports_dsa() {
+                gl::BindTextureUnit(unit as GLuint, self.id);
+            } else {
+                gl::ActiveTexture(Texture::unit_to_gl_const(unit));
+                gl::BindTexture(self.target, self.id);
+            }
         }
     }
 
@@ -52,42 +77,230 @@ impl Texture {
         }
     }
 
-    pub fn set_default_parameters(self) -> Self {
+    fn set_parameter(&self, name: GLenum, value: GLenum) {
+        unsafe {
+            if gl_supports_dsa() {
+                gl::TextureParameteri(self.id, name, value as GLint);
+            } else {
+                gl::BindTexture(self.target, self.id);
+                gl::TexParameteri(self.target, name, value as GLint);
+            }
+        }
+    }
+
+    /// Allocates the texture, uploads level 0 and generates the other levels if `mipmaps` is set
+    fn upload_2d(
+        &self,
+        (internal_format, format, data_type): PixelFormat,
+        width: u32,
+        height: u32,
+        pixels: *const std::ffi::c_void,
+        mipmaps: bool,
+    ) {
         unsafe {
-            gl::BindTexture(gl::TEXTURE_2D, self.id);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as GLint);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as GLint);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+            if gl_supports_dsa() {
+                let levels = if mipmaps {
+                    32 - width.max(height).leading_zeros()
+                } else {
+                    1
+                };
+                let (width, height) = (width as GLsizei, height as GLsizei);
+                gl::TextureStorage2D(self.id, levels as GLsizei, internal_format, width, height);
+                gl::TextureSubImage2D(self.id, 0, 0, 0, width, height, format, data_type, pixels);
+                if mipmaps {
+                    gl::GenerateTextureMipmap(self.id);
+                }
+            } else {
+                gl::BindTexture(gl::TEXTURE_2D, self.id);
+                gl::TexImage2D(
+                    gl::TEXTURE_2D,
+                    0,
+                    internal_format as GLint,
+                    width as GLint,
+                    height as GLint,
+                    0,
+                    format,
+                    data_type,
+                    pixels,
+                );
+                if mipmaps {
+                    gl::GenerateMipmap(gl::TEXTURE_2D);
+                }
+            }
         }
+    }
+
+    pub fn set_default_parameters(self) -> Self {
+        self.set_parameter(gl::TEXTURE_WRAP_S, gl::REPEAT);
+        self.set_parameter(gl::TEXTURE_WRAP_T, gl::REPEAT);
+        self.set_parameter(gl::TEXTURE_MIN_FILTER, gl::LINEAR);
+        self.set_parameter(gl::TEXTURE_MAG_FILTER, gl::LINEAR);
         self
     }
 
+    #[allow(dead_code)]
     pub fn set_image_2d(self, path: &str) -> Result<Self, TextureError> {
         // Load image from disk
         let img = load_image(path, true)?;
 
         // Send pixels to GPU
+        self.upload_2d(
+            (gl::SRGB8, gl::RGB, gl::UNSIGNED_BYTE),
+            img.width as u32,
+            img.height as u32,
+            img.data.as_ptr() as *const std::ffi::c_void,
+            true,
+        );
+
+        Ok(self)
+    }
+
+    /// Sets filtering and wrapping modes from a glTF sampler
+    pub fn set_gltf_sampler(self, sampler: &gltf::texture::Sampler) -> Self {
+        let mag_filter = sampler
+            .mag_filter()
+            .map_or(gl::LINEAR, |filter| filter.as_gl_enum());
+        let min_filter = sampler
+            .min_filter()
+            .map_or(gl::LINEAR_MIPMAP_LINEAR, |filter| filter.as_gl_enum());
+        self.set_parameter(gl::TEXTURE_WRAP_S, sampler.wrap_s().as_gl_enum());
+        self.set_parameter(gl::TEXTURE_WRAP_T, sampler.wrap_t().as_gl_enum());
+        self.set_parameter(gl::TEXTURE_MIN_FILTER, min_filter);
+        self.set_parameter(gl::TEXTURE_MAG_FILTER, mag_filter);
+        self
+    }
+
+    /// Uploads pixels decoded by `gltf::import`.
+    /// Color data (base color, emissive) should be uploaded with `srgb` set.
+    pub fn set_gltf_image_2d(self, image: &gltf::image::Data, srgb: bool) -> Self {
+        use gltf::image::Format::*;
+
+        let pixel_format = match image.format {
+            R8 => (gl::R8, gl::RED, gl::UNSIGNED_BYTE),
+            R8G8 => (gl::RG8, gl::RG, gl::UNSIGNED_BYTE),
+            R8G8B8 if srgb => (gl::SRGB8, gl::RGB, gl::UNSIGNED_BYTE),
+            R8G8B8 => (gl::RGB8, gl::RGB, gl::UNSIGNED_BYTE),
+            R8G8B8A8 if srgb => (gl::SRGB8_ALPHA8, gl::RGBA, gl::UNSIGNED_BYTE),
+            R8G8B8A8 => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE),
+            B8G8R8 if srgb => (gl::SRGB8, gl::BGR, gl::UNSIGNED_BYTE),
+            B8G8R8 => (gl::RGB8, gl::BGR, gl::UNSIGNED_BYTE),
+            B8G8R8A8 if srgb => (gl::SRGB8_ALPHA8, gl::BGRA, gl::UNSIGNED_BYTE),
+            B8G8R8A8 => (gl::RGBA8, gl::BGRA, gl::UNSIGNED_BYTE),
+            R16 => (gl::R16, gl::RED, gl::UNSIGNED_SHORT),
+            R16G16 => (gl::RG16, gl::RG, gl::UNSIGNED_SHORT),
+            R16G16B16 => (gl::RGB16, gl::RGB, gl::UNSIGNED_SHORT),
+            R16G16B16A16 => (gl::RGBA16, gl::RGBA, gl::UNSIGNED_SHORT),
+        };
+
+        // Single and dual channel images are swizzled to grey (+ alpha)
+        let swizzle = match pixel_format.1 {
+            gl::RED => Some([gl::RED, gl::RED, gl::RED, gl::ONE]),
+            gl::RG => Some([gl::RED, gl::RED, gl::RED, gl::GREEN]),
+            _ => None,
+        };
+
+        unsafe {
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1); // RGB rows are not always 4-byte aligned
+        }
+        self.upload_2d(
+            pixel_format,
+            image.width,
+            image.height,
+            image.pixels.as_ptr() as *const std::ffi::c_void,
+            true,
+        );
+        unsafe {
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+        }
+        if let Some(swizzle) = swizzle {
+            self.set_parameter(gl::TEXTURE_SWIZZLE_R, swizzle[0]);
+            self.set_parameter(gl::TEXTURE_SWIZZLE_G, swizzle[1]);
+            self.set_parameter(gl::TEXTURE_SWIZZLE_B, swizzle[2]);
+            self.set_parameter(gl::TEXTURE_SWIZZLE_A, swizzle[3]);
+        }
+
+        self
+    }
+
+    /// Fills the texture with a single pixel of the given color
+    pub fn set_color_2d(self, color: [u8; 4]) -> Self {
+        self.upload_2d(
+            (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE),
+            1,
+            1,
+            color.as_ptr() as *const std::ffi::c_void,
+            false,
+        );
+        self
+    }
+
+    /// Clamped, linearly filtered and without mipmaps
+    pub fn set_cube_map_parameters(self) -> Self {
+        self.set_parameter(gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE);
+        self.set_parameter(gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE);
+        self.set_parameter(gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE);
+        self.set_parameter(gl::TEXTURE_MIN_FILTER, gl::LINEAR);
+        self.set_parameter(gl::TEXTURE_MAG_FILTER, gl::LINEAR);
+        self
+    }
+
+    /// Faces in order: right, left, top, bottom, front, back. All must be the same size
+    pub fn set_cube_map_images(self, paths: [&str; 6]) -> Result<Self, TextureError> {
+        let images = paths
+            .iter()
+            .map(|path| load_image(path, false))
+            .collect::<Result<Vec<_>, _>>()?;
+
         unsafe {
-            gl::BindTexture(gl::TEXTURE_2D, self.id);
-            gl::TexImage2D(
-                gl::TEXTURE_2D,
-                0,
-                gl::SRGB8 as GLint,
-                img.width as GLint,
-                img.height as GLint,
-                0,
-                gl::RGB,
-                gl::UNSIGNED_BYTE,
-                img.data.as_ptr() as *const std::ffi::c_void,
-            );
-            gl::GenerateMipmap(gl::TEXTURE_2D);
+            if gl_supports_dsa() {
+                let (width, height) = (images[0].width as GLsizei, images[0].height as GLsizei);
+                gl::TextureStorage2D(self.id, 1, gl::SRGB8, width, height);
+                for (face, img) in images.iter().enumerate() {
+                    gl::TextureSubImage3D(
+                        self.id,
+                        0,
+                        0,
+                        0,
+                        face as GLint,
+                        img.width as GLsizei,
+                        img.height as GLsizei,
+                        1,
+                        gl::RGB,
+                        gl::UNSIGNED_BYTE,
+                        img.data.as_ptr() as *const std::ffi::c_void,
+                    );
+                }
+            } else {
+                gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.id);
+                for (face, img) in images.iter().enumerate() {
+                    gl::TexImage2D(
+                        gl::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+                        0,
+                        gl::SRGB as GLint,
+                        img.width as GLint,
+                        img.height as GLint,
+                        0,
+                        gl::RGB,
+                        gl::UNSIGNED_BYTE,
+                        img.data.as_ptr() as *const std::ffi::c_void,
+                    );
+                }
+            }
         }
 
         Ok(self)
     }
 }
 
+impl Drop for Texture {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.id);
+        }
+        track_gl_object_deleted(GlObject::Texture, self.id);
+    }
+}
+
 pub fn load_image(path: &str, flip: bool) -> Result<Image<u8>, TextureError> {
     let flip = if flip { 1 } else { 0 };
     unsafe {
@@ -0,0 +1,146 @@
+use std::mem::{offset_of, size_of};
+
+use bytemuck::{Pod, Zeroable};
+use gl::types::*;
+use glam::{Mat4, Vec3};
+
+/// A std140 uniform block shared by all programs, see assets/shaders/common/blocks.glsl.
+/// Programs get it bound to `BINDING` when they're linked
+pub trait UniformBlock: Pod {
+    /// Block name in GLSL
+    const NAME: &'static str;
+    const BINDING: GLuint;
+
+    /// GLSL names of the block members and their offsets in Self
+    fn members() -> Vec<(&'static str, usize)>;
+}
+
+/// Rust side layout of a uniform block, checked against the one reflected from a program
+pub struct BlockLayout {
+    pub name: &'static str,
+    pub binding: GLuint,
+    pub size: usize,
+    pub members: Vec<(&'static str, usize)>,
+}
+
+impl BlockLayout {
+    pub fn of<T: UniformBlock>() -> Self {
+        BlockLayout {
+            name: T::NAME,
+            binding: T::BINDING,
+            size: size_of::<T>(),
+            members: T::members(),
+        }
+    }
+
+    /// Offset of a member in the Rust struct
+    pub fn offset(&self, member: &str) -> Option<usize> {
+        self.members
+            .iter()
+            .find(|(name, _)| *name == member)
+            .map(|&(_, offset)| offset)
+    }
+}
+
+/// Layout of the shared block with the given GLSL name
+pub fn shared_block(name: &str) -> Option<BlockLayout> {
+    vec![
+        BlockLayout::of::<CameraBlock>(),
+        BlockLayout::of::<LightsBlock>(),
+        BlockLayout::of::<MaterialDefaultsBlock>(),
+    ]
+    .into_iter()
+    .find(|layout| layout.name == name)
+}
+
+// ==================================== Blocks ====================================================
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct CameraBlock {
+    pub proj: Mat4,
+    pub view: Mat4,
+}
+
+impl UniformBlock for CameraBlock {
+    const NAME: &'static str = "Camera";
+    const BINDING: GLuint = 0;
+
+    fn members() -> Vec<(&'static str, usize)> {
+        vec![
+            ("proj", offset_of!(Self, proj)),
+            ("view", offset_of!(Self, view)),
+        ]
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct LightsBlock {
+    /// Direction of the directional light in view space
+    pub direction: [f32; 3],
+    _pad0: f32,
+    /// Phong terms
+    pub ambient: [f32; 3],
+    _pad1: f32,
+    pub diffuse: [f32; 3],
+    _pad2: f32,
+    pub specular: [f32; 3],
+    _pad3: f32,
+    /// Light color for the PBR shaders, which use `ambient` too
+    pub radiance: [f32; 3],
+    _pad4: f32,
+}
+
+impl LightsBlock {
+    pub fn new(
+        direction: Vec3,
+        ambient: Vec3,
+        diffuse: Vec3,
+        specular: Vec3,
+        radiance: Vec3,
+    ) -> Self {
+        LightsBlock {
+            direction: direction.to_array(),
+            ambient: ambient.to_array(),
+            diffuse: diffuse.to_array(),
+            specular: specular.to_array(),
+            radiance: radiance.to_array(),
+            ..LightsBlock::zeroed()
+        }
+    }
+}
+
+impl UniformBlock for LightsBlock {
+    const NAME: &'static str = "Lights";
+    const BINDING: GLuint = 1;
+
+    fn members() -> Vec<(&'static str, usize)> {
+        vec![
+            ("light_direction", offset_of!(Self, direction)),
+            ("light_ambient", offset_of!(Self, ambient)),
+            ("light_diffuse", offset_of!(Self, diffuse)),
+            ("light_specular", offset_of!(Self, specular)),
+            ("light_radiance", offset_of!(Self, radiance)),
+        ]
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+pub struct MaterialDefaultsBlock {
+    pub specular: [f32; 3],
+    pub shininess: f32,
+}
+
+impl UniformBlock for MaterialDefaultsBlock {
+    const NAME: &'static str = "MaterialDefaults";
+    const BINDING: GLuint = 2;
+
+    fn members() -> Vec<(&'static str, usize)> {
+        vec![
+            ("default_specular", offset_of!(Self, specular)),
+            ("default_shininess", offset_of!(Self, shininess)),
+        ]
+    }
+}
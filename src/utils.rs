@@ -1,6 +1,11 @@
 #![macro_use]
 #![allow(dead_code)]
 
+use std::collections::BTreeSet;
+use std::sync::{Mutex, OnceLock};
+
+use gl::types::GLuint;
+
 pub fn gl_check_error(file: &str, line: u32) {
     let error_code = unsafe { gl::GetError() };
     if error_code != gl::NO_ERROR {
@@ -17,6 +22,95 @@ pub fn gl_check_error(file: &str, line: u32) {
     }
 }
 
+/// Version of the current context as (major, minor)
+pub fn gl_version() -> (i32, i32) {
+    let (mut major, mut minor) = (0, 0);
+    unsafe {
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+    }
+    (major, minor)
+}
+
+pub fn gl_has_extension(name: &str) -> bool {
+    let mut count = 0;
+    unsafe {
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+    }
+    (0..count as u32).any(|i| {
+        let extension =
+            unsafe { std::ffi::CStr::from_ptr(gl::GetStringi(gl::EXTENSIONS, i) as *const _) };
+        extension.to_bytes() == name.as_bytes()
+    })
+}
+
+/// glMultiDrawElementsIndirect (4.3) with gl_DrawIDARB available in shaders
+pub fn gl_supports_indirect_draws() -> bool {
+    gl_version() >= (4, 3) && gl_has_extension("GL_ARB_shader_draw_parameters")
+}
+
+/// Direct state access (4.5): objects are edited by name instead of being bound first.
+/// Checked once, there's only one context
+pub fn gl_supports_dsa() -> bool {
+    static DSA: OnceLock<bool> = OnceLock::new();
+    *DSA.get_or_init(|| gl_version() >= (4, 5))
+}
+
+/// Separate vertex formats and buffer bindings (4.3), so a buffer can be rebound
+/// without specifying the attributes again. Checked once, like DSA
+pub fn gl_supports_attrib_binding() -> bool {
+    static ATTRIB_BINDING: OnceLock<bool> = OnceLock::new();
+    *ATTRIB_BINDING
+        .get_or_init(|| gl_version() >= (4, 3) || gl_has_extension("GL_ARB_vertex_attrib_binding"))
+}
+
+/// glBufferStorage, needed for persistently mapped buffers
+pub fn gl_supports_buffer_storage() -> bool {
+    gl_version() >= (4, 4) || gl_has_extension("GL_ARB_buffer_storage")
+}
+
+// ==================================== Leak tracking =============================================
+
+/// Kinds of GL objects which are tracked in debug builds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GlObject {
+    Buffer,
+    VertexArray,
+    Texture,
+    Shader,
+    Program,
+}
+
+/// Objects created and not yet deleted
+static LIVE_GL_OBJECTS: Mutex<BTreeSet<(GlObject, GLuint)>> = Mutex::new(BTreeSet::new());
+
+pub fn track_gl_object_created(kind: GlObject, id: GLuint) {
+    if cfg!(debug_assertions) {
+        LIVE_GL_OBJECTS.lock().unwrap().insert((kind, id));
+    }
+}
+
+pub fn track_gl_object_deleted(kind: GlObject, id: GLuint) {
+    if cfg!(debug_assertions) {
+        LIVE_GL_OBJECTS.lock().unwrap().remove(&(kind, id));
+    }
+}
+
+/// Print the GL objects which are still alive. Call at shutdown, after everything is dropped
+pub fn report_gl_leaks() {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    let live = LIVE_GL_OBJECTS.lock().unwrap();
+    if live.is_empty() {
+        return;
+    }
+    eprintln!("{} GL objects were not deleted:", live.len());
+    for (kind, id) in live.iter() {
+        eprintln!("    {:?} {}", kind, id);
+    }
+}
+
 #[allow(unused_macros)]
 macro_rules! gl_check_error {
     () => {